@@ -2,14 +2,29 @@ use speedy2d::{
     color::Color,
     window::{
         KeyScancode, ModifiersState, MouseButton, MouseScrollDistance, VirtualKeyCode,
-        WindowHandler, WindowHelper, WindowStartupInfo,
+        WindowFullscreenMode, WindowHandler, WindowHelper, WindowStartupInfo,
     },
     Graphics2D,
 };
 
-use glam::{UVec2, Vec2};
+use glam::{DVec2, UVec2, Vec2};
+use strum_macros::{Display, EnumString};
 
-use crate::game::Game;
+use crate::game::{Game, WindowRequest};
+
+/// Pixel scrolling from trackpads is turned into this many pixels per wheel line.
+const PIXELS_PER_LINE: f64 = 20.0;
+const LINES_PER_PAGE: f64 = 10.0;
+
+/// What scrolling does. Holding ctrl always zooms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum ScrollAction {
+    /// Vertical scrolling goes to the wheel bindings (zoom by default), horizontal pans.
+    Zoom,
+    /// Scrolling pans the image both ways.
+    Pan,
+}
 
 pub struct App {
     viewport_size: UVec2,
@@ -20,6 +35,12 @@ pub struct App {
     keyboard: Keyboard,
     is_fullscreen: bool,
     is_inputting_text: bool,
+    /// For mouse wheels, which scroll by lines or pages.
+    wheel_scroll: ScrollAction,
+    /// For trackpads, which scroll by pixels.
+    trackpad_scroll: ScrollAction,
+    background: Color,
+    fullscreen_background: Color,
 
     game: Game,
 }
@@ -33,8 +54,12 @@ impl App {
             sleep_ms_per_frame: config.sleep_ms_per_frame,
             mouse: Mouse::new(),
             keyboard: Keyboard::new(),
-            is_fullscreen: false,
+            is_fullscreen: config.fullscreen,
             is_inputting_text: false,
+            wheel_scroll: config.wheel_scroll,
+            trackpad_scroll: config.trackpad_scroll,
+            background: config.background,
+            fullscreen_background: config.fullscreen_background,
 
             game: Game::new(config),
         }
@@ -45,12 +70,23 @@ impl App {
             self.setup(graphics);
         }
         self.input();
-        if self.keyboard.pressed.contains(&VirtualKeyCode::Escape) {
-            helper.terminate_loop();
-            return;
-        }
-
         self.update(graphics);
+        for request in self.game.window_requests() {
+            match request {
+                WindowRequest::SetTitle(title) => helper.set_title(title),
+                WindowRequest::ToggleFullscreen => {
+                    helper.set_fullscreen_mode(if self.is_fullscreen {
+                        WindowFullscreenMode::Windowed
+                    } else {
+                        WindowFullscreenMode::FullscreenBorderless
+                    });
+                }
+                WindowRequest::Quit => {
+                    helper.terminate_loop();
+                    return;
+                }
+            }
+        }
 
         self.draw(graphics);
         self.current_frame += 1;
@@ -70,10 +106,15 @@ impl App {
     pub fn update(&mut self, graphics: &mut Graphics2D) {
         self.game.update(graphics, self.current_frame);
         self.keyboard.clear();
+        self.mouse.clear();
     }
 
     pub fn draw(&self, graphics: &mut Graphics2D) {
-        graphics.clear_screen(Color::from_gray(0.3));
+        graphics.clear_screen(if self.is_fullscreen {
+            self.fullscreen_background
+        } else {
+            self.background
+        });
         self.game.draw(graphics);
     }
 }
@@ -109,6 +150,7 @@ impl WindowHandler for App {
             println!("App is now windowed!");
         }
         self.is_fullscreen = fullscreen;
+        self.game.set_fullscreen(fullscreen);
     }
 
     fn on_draw(&mut self, helper: &mut WindowHelper<()>, graphics: &mut Graphics2D) {
@@ -132,19 +174,31 @@ impl WindowHandler for App {
         _helper: &mut WindowHelper<()>,
         distance: MouseScrollDistance,
     ) {
-        match distance {
-            MouseScrollDistance::Lines { x, y, z } => {
-                if x != 0.0 {
-                    eprintln!("Unsupported input: MouseScroll on X coordinate!");
-                }
-                if z != 0.0 {
-                    eprintln!("Unsupported input: MouseScroll on Z coordinate!");
-                }
-                if y != 0.0 {
-                    self.mouse.scroll_accumulated += y;
-                }
+        let (lines, z, action) = match distance {
+            MouseScrollDistance::Lines { x, y, z } => (DVec2::new(x, y), z, self.wheel_scroll),
+            MouseScrollDistance::Pixels { x, y, z } => {
+                (DVec2::new(x, y) / PIXELS_PER_LINE, z, self.trackpad_scroll)
+            }
+            MouseScrollDistance::Pages { x, y, z } => {
+                (DVec2::new(x, y) * LINES_PER_PAGE, z, self.wheel_scroll)
+            }
+        };
+        if z != 0.0 {
+            eprintln!("Unsupported input: MouseScroll on Z coordinate!");
+        }
+        let action = if self.keyboard.modifiers.ctrl() {
+            ScrollAction::Zoom
+        } else {
+            action
+        };
+        // Positive y scrolls up, which shows what is above, so the image moves down.
+        let pan = DVec2::new(-lines.x, lines.y) * PIXELS_PER_LINE;
+        match action {
+            ScrollAction::Zoom => {
+                self.mouse.scroll_accumulated += lines.y;
+                self.mouse.scroll_pan.x += pan.as_vec2().x;
             }
-            other => eprintln!("Unsupported input: {other:?}"),
+            ScrollAction::Pan => self.mouse.scroll_pan += pan.as_vec2(),
         }
     }
 
@@ -235,7 +289,11 @@ pub struct Mouse {
     pub position: Vec2,
     grabbed: bool,
     pub pressed: Vec<MouseButton>,
+    pub just_pressed: Vec<MouseButton>,
+    /// Wheel lines scrolled vertically, for the wheel bindings.
     pub scroll_accumulated: f64,
+    /// Screen pixels scrolled for panning.
+    pub scroll_pan: Vec2,
 }
 
 impl Mouse {
@@ -244,15 +302,22 @@ impl Mouse {
             position: Vec2::ZERO,
             grabbed: false,
             pressed: Vec::new(),
+            just_pressed: Vec::new(),
             scroll_accumulated: 0.0,
+            scroll_pan: Vec2::ZERO,
         }
     }
 
+    pub fn clear(&mut self) {
+        self.just_pressed.clear();
+    }
+
     pub fn press(&mut self, button: MouseButton) {
         if self.pressed.contains(&button) {
             println!("Pressed {button:?} without releasing it first!");
         } else {
             self.pressed.push(button);
+            self.just_pressed.push(button);
         }
     }
 
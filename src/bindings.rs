@@ -0,0 +1,528 @@
+use std::fmt;
+
+use speedy2d::window::{ModifiersState, MouseButton, VirtualKeyCode};
+use strum::IntoEnumIterator;
+use strum_macros::{Display, EnumIter, EnumString};
+
+use crate::app::{Keyboard, Mouse};
+
+/// Everything a key, mouse button or the wheel can be bound to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, EnumString, EnumIter)]
+#[strum(serialize_all = "snake_case")]
+pub enum Action {
+    Next,
+    Prev,
+    NextDir,
+    PrevDir,
+    Quit,
+    ToggleFullscreen,
+    ToggleHud,
+    ToggleSlideshow,
+    /// Switches between the single image and a grid of thumbnails.
+    ToggleGallery,
+    /// Opens the image picked in the gallery.
+    Open,
+    Invert,
+    CycleViewMode,
+    CycleFilter,
+    CycleSort,
+    ReverseSort,
+    RotateLeft,
+    RotateRight,
+    /// Turns the image steadily while held.
+    RotateLeftFine,
+    RotateRightFine,
+    FlipHorizontal,
+    FlipVertical,
+    TogglePause,
+    NextFrame,
+    PrevFrame,
+    Faster,
+    Slower,
+    ZoomIn,
+    ZoomOut,
+    /// Fits the image to the window again.
+    ResetZoom,
+    /// Drags the image along with the mouse while held.
+    Pan,
+    /// Moves the view while held, faster with shift.
+    PanLeft,
+    PanRight,
+    PanUp,
+    PanDown,
+}
+
+impl Action {
+    /// Actions that keep going while their binding is held, instead of happening once.
+    const fn is_held(self) -> bool {
+        matches!(
+            self,
+            Self::Pan
+                | Self::PanLeft
+                | Self::PanRight
+                | Self::PanUp
+                | Self::PanDown
+                | Self::RotateLeftFine
+                | Self::RotateRightFine
+        )
+    }
+
+    const fn is_zoom(self) -> bool {
+        matches!(self, Self::ZoomIn | Self::ZoomOut)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Trigger {
+    Key(VirtualKeyCode),
+    Mouse(MouseButton),
+    WheelUp,
+    WheelDown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+struct Modifiers {
+    ctrl: bool,
+    shift: bool,
+    alt: bool,
+}
+
+impl Modifiers {
+    fn of(state: &ModifiersState) -> Self {
+        Self {
+            ctrl: state.ctrl(),
+            shift: state.shift(),
+            alt: state.alt(),
+        }
+    }
+}
+
+/// A key, mouse button or wheel direction, with the modifiers that have to be held for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Binding {
+    trigger: Trigger,
+    modifiers: Modifiers,
+}
+
+impl Binding {
+    /// Parses bindings like `e`, `shift+o`, `ctrl+alt+page_down`, `mouse_left` or `wheel_up`.
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut modifiers = Modifiers::default();
+        let mut parts: Vec<&str> = text.split('+').map(str::trim).collect();
+        let name = parts.pop().unwrap_or_default().to_lowercase();
+        for modifier in parts {
+            match modifier.to_lowercase().as_str() {
+                "ctrl" | "control" => modifiers.ctrl = true,
+                "shift" => modifiers.shift = true,
+                "alt" => modifiers.alt = true,
+                _ => return Err(format!("Unknown modifier {modifier:?} in {text:?}")),
+            }
+        }
+        let trigger = match name.as_str() {
+            "mouse_left" => Trigger::Mouse(MouseButton::Left),
+            "mouse_right" => Trigger::Mouse(MouseButton::Right),
+            "mouse_middle" => Trigger::Mouse(MouseButton::Middle),
+            "mouse_back" => Trigger::Mouse(MouseButton::Back),
+            "mouse_forward" => Trigger::Mouse(MouseButton::Forward),
+            "wheel_up" => Trigger::WheelUp,
+            "wheel_down" => Trigger::WheelDown,
+            name => KEYS
+                .iter()
+                .find(|(key_name, _)| *key_name == name)
+                .map(|(_, key)| Trigger::Key(*key))
+                .ok_or_else(|| format!("Unknown key {name:?} in {text:?}"))?,
+        };
+        Ok(Self { trigger, modifiers })
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.modifiers.ctrl {
+            write!(f, "ctrl+")?;
+        }
+        if self.modifiers.shift {
+            write!(f, "shift+")?;
+        }
+        if self.modifiers.alt {
+            write!(f, "alt+")?;
+        }
+        match self.trigger {
+            Trigger::Key(key) => match KEYS.iter().find(|(_, known)| *known == key) {
+                Some((name, _)) => write!(f, "{name}"),
+                None => write!(f, "{key:?}"),
+            },
+            Trigger::Mouse(button) => write!(f, "mouse_{}", format!("{button:?}").to_lowercase()),
+            Trigger::WheelUp => write!(f, "wheel_up"),
+            Trigger::WheelDown => write!(f, "wheel_down"),
+        }
+    }
+}
+
+/// Which bindings trigger which actions, read from the `[keys]` section of config.ini.
+#[derive(Debug, Clone)]
+pub struct Bindings {
+    bindings: Vec<(Binding, Action)>,
+}
+
+impl Bindings {
+    /// Replaces the bindings of `action` with the comma-separated ones in `value`.
+    /// An empty value unbinds it.
+    pub fn set(&mut self, action: Action, value: &str) -> Result<(), String> {
+        let bindings = value
+            .split(',')
+            .map(str::trim)
+            .filter(|text| !text.is_empty())
+            .map(Binding::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        self.bindings.retain(|(_, bound)| *bound != action);
+        self.bindings
+            .extend(bindings.into_iter().map(|binding| (binding, action)));
+        Ok(())
+    }
+
+    /// Fails if one binding is used for more than one action. Only the first of those
+    /// actions would ever happen.
+    pub fn validate(&self) -> Result<(), String> {
+        let mut duplicates = Vec::new();
+        for (index, (binding, action)) in self.bindings.iter().enumerate() {
+            let first = self.bindings[..index]
+                .iter()
+                .find(|(other, other_action)| other == binding && other_action != action);
+            if let Some((_, other_action)) = first {
+                duplicates.push(format!(
+                    "{binding} is bound to both {other_action} and {action}"
+                ));
+            }
+        }
+        if duplicates.is_empty() {
+            Ok(())
+        } else {
+            Err(duplicates.join("\n"))
+        }
+    }
+
+    /// Whether the modifiers held right now fit `binding`. A binding without modifiers
+    /// works with any of them held, unless the same key is bound with exactly those,
+    /// so "o" and "shift+o" can do different things while shift+drag still pans.
+    fn is_active(&self, binding: Binding, current: Modifiers) -> bool {
+        binding.modifiers == current
+            || (binding.modifiers == Modifiers::default()
+                && !self.bindings.iter().any(|(other, _)| {
+                    other.trigger == binding.trigger && other.modifiers == current
+                }))
+    }
+
+    /// The actions whose key or button was pressed since last frame, or whose wheel
+    /// direction was scrolled. Zooming with the wheel is left to [`Self::wheel`].
+    pub fn triggered(&self, keyboard: &Keyboard, mouse: &Mouse, scroll_delta: f64) -> Vec<Action> {
+        let current = Modifiers::of(&keyboard.modifiers);
+        let mut actions = Vec::new();
+        for (binding, action) in &self.bindings {
+            if action.is_held() || !self.is_active(*binding, current) {
+                continue;
+            }
+            let triggered = match binding.trigger {
+                Trigger::Key(key) => keyboard.just_pressed.contains(&key),
+                Trigger::Mouse(button) => mouse.just_pressed.contains(&button),
+                Trigger::WheelUp => !action.is_zoom() && scroll_delta > 0.0,
+                Trigger::WheelDown => !action.is_zoom() && scroll_delta < 0.0,
+            };
+            if triggered && !actions.contains(action) {
+                actions.push(*action);
+            }
+        }
+        actions
+    }
+
+    /// Whether a binding of `action` was pressed since last frame. Unlike
+    /// [`Self::triggered`], this also works for actions that are usually held.
+    pub fn pressed(&self, action: Action, keyboard: &Keyboard, mouse: &Mouse) -> bool {
+        let current = Modifiers::of(&keyboard.modifiers);
+        self.bindings.iter().any(|(binding, bound)| {
+            *bound == action
+                && self.is_active(*binding, current)
+                && match binding.trigger {
+                    Trigger::Key(key) => keyboard.just_pressed.contains(&key),
+                    Trigger::Mouse(button) => mouse.just_pressed.contains(&button),
+                    Trigger::WheelUp | Trigger::WheelDown => false,
+                }
+        })
+    }
+
+    /// Whether a binding of `action` is held down right now.
+    pub fn held(&self, action: Action, keyboard: &Keyboard, mouse: &Mouse) -> bool {
+        let current = Modifiers::of(&keyboard.modifiers);
+        self.bindings.iter().any(|(binding, bound)| {
+            *bound == action
+                && self.is_active(*binding, current)
+                && match binding.trigger {
+                    Trigger::Key(key) => keyboard.pressed.contains(&key),
+                    Trigger::Mouse(button) => mouse.pressed.contains(&button),
+                    Trigger::WheelUp | Trigger::WheelDown => false,
+                }
+        })
+    }
+
+    /// How many lines the wheel was scrolled in the direction bound to `action`.
+    pub fn wheel(&self, action: Action, keyboard: &Keyboard, scroll_delta: f64) -> f64 {
+        let current = Modifiers::of(&keyboard.modifiers);
+        self.bindings
+            .iter()
+            .filter(|(binding, bound)| *bound == action && self.is_active(*binding, current))
+            .map(|(binding, _)| match binding.trigger {
+                Trigger::WheelUp => scroll_delta.max(0.0),
+                Trigger::WheelDown => (-scroll_delta).max(0.0),
+                Trigger::Key(_) | Trigger::Mouse(_) => 0.0,
+            })
+            .sum()
+    }
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        let mut bindings = Self {
+            bindings: Vec::new(),
+        };
+        for action in Action::iter() {
+            let value = match action {
+                Action::Next => "e",
+                Action::Prev => "q",
+                Action::NextDir => "page_down",
+                Action::PrevDir => "page_up",
+                Action::Quit => "escape",
+                Action::ToggleFullscreen => "f, f11",
+                Action::ToggleHud => "i",
+                Action::ToggleSlideshow => "s",
+                Action::ToggleGallery => "g",
+                Action::Open => "return",
+                Action::Invert => "r",
+                Action::CycleViewMode => "v",
+                Action::CycleFilter => "t",
+                Action::CycleSort => "o",
+                Action::ReverseSort => "shift+o",
+                Action::RotateLeft => "z",
+                Action::RotateRight => "x",
+                Action::RotateLeftFine => "shift+z",
+                Action::RotateRightFine => "shift+x",
+                Action::FlipHorizontal => "m",
+                Action::FlipVertical => "shift+m",
+                Action::TogglePause => "space",
+                Action::NextFrame => "period",
+                Action::PrevFrame => "comma",
+                Action::Faster => "rbracket",
+                Action::Slower => "lbracket",
+                // Scrolling up zooms out, like it always has.
+                Action::ZoomIn => "wheel_down, plus, equals, numpad_add",
+                Action::ZoomOut => "wheel_up, minus, numpad_subtract",
+                Action::ResetZoom => "0",
+                Action::Pan => "mouse_left",
+                Action::PanLeft => "left, h",
+                Action::PanRight => "right, l",
+                Action::PanUp => "up, k",
+                Action::PanDown => "down, j",
+            };
+            bindings
+                .set(action, value)
+                .expect("default bindings are valid");
+        }
+        bindings
+    }
+}
+
+/// Key names as written in config.ini.
+const KEYS: &[(&str, VirtualKeyCode)] = &[
+    ("a", VirtualKeyCode::A),
+    ("b", VirtualKeyCode::B),
+    ("c", VirtualKeyCode::C),
+    ("d", VirtualKeyCode::D),
+    ("e", VirtualKeyCode::E),
+    ("f", VirtualKeyCode::F),
+    ("g", VirtualKeyCode::G),
+    ("h", VirtualKeyCode::H),
+    ("i", VirtualKeyCode::I),
+    ("j", VirtualKeyCode::J),
+    ("k", VirtualKeyCode::K),
+    ("l", VirtualKeyCode::L),
+    ("m", VirtualKeyCode::M),
+    ("n", VirtualKeyCode::N),
+    ("o", VirtualKeyCode::O),
+    ("p", VirtualKeyCode::P),
+    ("q", VirtualKeyCode::Q),
+    ("r", VirtualKeyCode::R),
+    ("s", VirtualKeyCode::S),
+    ("t", VirtualKeyCode::T),
+    ("u", VirtualKeyCode::U),
+    ("v", VirtualKeyCode::V),
+    ("w", VirtualKeyCode::W),
+    ("x", VirtualKeyCode::X),
+    ("y", VirtualKeyCode::Y),
+    ("z", VirtualKeyCode::Z),
+    ("0", VirtualKeyCode::Key0),
+    ("1", VirtualKeyCode::Key1),
+    ("2", VirtualKeyCode::Key2),
+    ("3", VirtualKeyCode::Key3),
+    ("4", VirtualKeyCode::Key4),
+    ("5", VirtualKeyCode::Key5),
+    ("6", VirtualKeyCode::Key6),
+    ("7", VirtualKeyCode::Key7),
+    ("8", VirtualKeyCode::Key8),
+    ("9", VirtualKeyCode::Key9),
+    ("f1", VirtualKeyCode::F1),
+    ("f2", VirtualKeyCode::F2),
+    ("f3", VirtualKeyCode::F3),
+    ("f4", VirtualKeyCode::F4),
+    ("f5", VirtualKeyCode::F5),
+    ("f6", VirtualKeyCode::F6),
+    ("f7", VirtualKeyCode::F7),
+    ("f8", VirtualKeyCode::F8),
+    ("f9", VirtualKeyCode::F9),
+    ("f10", VirtualKeyCode::F10),
+    ("f11", VirtualKeyCode::F11),
+    ("f12", VirtualKeyCode::F12),
+    ("escape", VirtualKeyCode::Escape),
+    ("space", VirtualKeyCode::Space),
+    ("return", VirtualKeyCode::Return),
+    ("enter", VirtualKeyCode::Return),
+    ("tab", VirtualKeyCode::Tab),
+    ("backspace", VirtualKeyCode::Backspace),
+    ("delete", VirtualKeyCode::Delete),
+    ("insert", VirtualKeyCode::Insert),
+    ("home", VirtualKeyCode::Home),
+    ("end", VirtualKeyCode::End),
+    ("page_up", VirtualKeyCode::PageUp),
+    ("page_down", VirtualKeyCode::PageDown),
+    ("left", VirtualKeyCode::Left),
+    ("right", VirtualKeyCode::Right),
+    ("up", VirtualKeyCode::Up),
+    ("down", VirtualKeyCode::Down),
+    ("comma", VirtualKeyCode::Comma),
+    ("period", VirtualKeyCode::Period),
+    ("minus", VirtualKeyCode::Minus),
+    ("plus", VirtualKeyCode::Plus),
+    ("equals", VirtualKeyCode::Equals),
+    ("slash", VirtualKeyCode::Slash),
+    ("backslash", VirtualKeyCode::Backslash),
+    ("semicolon", VirtualKeyCode::Semicolon),
+    ("apostrophe", VirtualKeyCode::Apostrophe),
+    ("grave", VirtualKeyCode::Grave),
+    ("lbracket", VirtualKeyCode::LBracket),
+    ("rbracket", VirtualKeyCode::RBracket),
+    ("numpad0", VirtualKeyCode::Numpad0),
+    ("numpad1", VirtualKeyCode::Numpad1),
+    ("numpad2", VirtualKeyCode::Numpad2),
+    ("numpad3", VirtualKeyCode::Numpad3),
+    ("numpad4", VirtualKeyCode::Numpad4),
+    ("numpad5", VirtualKeyCode::Numpad5),
+    ("numpad6", VirtualKeyCode::Numpad6),
+    ("numpad7", VirtualKeyCode::Numpad7),
+    ("numpad8", VirtualKeyCode::Numpad8),
+    ("numpad9", VirtualKeyCode::Numpad9),
+    ("numpad_add", VirtualKeyCode::NumpadAdd),
+    ("numpad_subtract", VirtualKeyCode::NumpadSubtract),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHIFT: Modifiers = Modifiers {
+        ctrl: false,
+        shift: true,
+        alt: false,
+    };
+
+    fn binding(text: &str) -> Binding {
+        Binding::parse(text).unwrap()
+    }
+
+    /// A keyboard with `keys` pressed this frame and no modifiers held.
+    fn keyboard(keys: &[VirtualKeyCode]) -> Keyboard {
+        Keyboard {
+            buffer: Vec::new(),
+            modifiers: ModifiersState::default(),
+            pressed: keys.to_vec(),
+            just_pressed: keys.to_vec(),
+            just_released: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn parses_modifiers_and_names() {
+        let parsed = binding("Ctrl + Alt+Page_Down");
+        assert_eq!(parsed.trigger, Trigger::Key(VirtualKeyCode::PageDown));
+        let ctrl_alt = Modifiers {
+            ctrl: true,
+            shift: false,
+            alt: true,
+        };
+        assert_eq!(parsed.modifiers, ctrl_alt);
+        assert_eq!(parsed.to_string(), "ctrl+alt+page_down");
+        assert_eq!(
+            binding("mouse_left").trigger,
+            Trigger::Mouse(MouseButton::Left)
+        );
+        assert_eq!(binding("wheel_up").trigger, Trigger::WheelUp);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(Binding::parse("hyper+o").unwrap_err().contains("modifier"));
+        assert!(Binding::parse("shift+nope")
+            .unwrap_err()
+            .contains("Unknown key"));
+        assert!(Binding::parse("").is_err());
+        assert!(Binding::parse("shift+").is_err());
+
+        // A bad value leaves the action's bindings alone.
+        let mut bindings = Bindings::default();
+        assert!(bindings.set(Action::Next, "e, bogus").is_err());
+        let pressed = bindings.triggered(&keyboard(&[VirtualKeyCode::E]), &Mouse::new(), 0.0);
+        assert_eq!(pressed, [Action::Next]);
+    }
+
+    #[test]
+    fn duplicates_are_reported() {
+        let mut bindings = Bindings::default();
+        assert!(bindings.validate().is_ok());
+        bindings.set(Action::Quit, "escape, q").unwrap();
+        assert_eq!(
+            bindings.validate().unwrap_err(),
+            "q is bound to both prev and quit"
+        );
+
+        // The same binding twice for one action is harmless.
+        let mut bindings = Bindings::default();
+        bindings.set(Action::Quit, "escape, escape").unwrap();
+        assert!(bindings.validate().is_ok());
+    }
+
+    #[test]
+    fn plain_key_yields_to_its_shifted_binding() {
+        let bindings = Bindings::default();
+        assert!(bindings.is_active(binding("o"), Modifiers::default()));
+        assert!(!bindings.is_active(binding("o"), SHIFT));
+        assert!(bindings.is_active(binding("shift+o"), SHIFT));
+        assert!(!bindings.is_active(binding("shift+o"), Modifiers::default()));
+
+        let pressed = bindings.triggered(&keyboard(&[VirtualKeyCode::O]), &Mouse::new(), 0.0);
+        assert_eq!(pressed, [Action::CycleSort]);
+    }
+
+    #[test]
+    fn shift_still_pans() {
+        let bindings = Bindings::default();
+        assert!(bindings.is_active(binding("mouse_left"), SHIFT));
+        assert!(bindings.is_active(binding("left"), SHIFT));
+        let mut mouse = Mouse::new();
+        mouse.pressed.push(MouseButton::Left);
+        assert!(bindings.held(Action::Pan, &keyboard(&[]), &mouse));
+
+        // Unless shift+left does something else.
+        let mut bindings = Bindings::default();
+        bindings
+            .set(Action::PrevDir, "page_up, shift+left")
+            .unwrap();
+        assert!(!bindings.is_active(binding("left"), SHIFT));
+    }
+}
@@ -0,0 +1,379 @@
+use std::str::FromStr;
+
+use crate::config::Config;
+use crate::game::camera::ViewMode;
+use crate::game::filters::FilterKind;
+use crate::game::sorting::{SortKey, SortOrder};
+
+pub const USAGE: &str = "\
+Usage: pfiew [OPTIONS] [PATH]...
+
+Shows the images in the given directories and files, the current directory by default.
+
+Options:
+  --config <path>     Read settings from this file instead of config.ini
+  --title <text>      Window title
+  --width <pixels>    Window width
+  --height <pixels>   Window height
+  --fullscreen        Start in fullscreen
+  --sleep-ms-per-frame <ms> Pause between frames
+  --cache-mb <mb>     Memory for decoded images
+  --view-mode <mode>  fit, fill, actual_size, fit_width, fit_height
+  --start-at <file>   Open this file first
+  --recursive         Also show images in subdirectories
+  --max-depth <n>     Go at most this many directories deep, implies --recursive
+  --hidden            Include hidden files and directories
+  --exclude <pattern> Skip matching files and directories, can be repeated
+  --sort <key>        Sort by: name, modified, size, extension, dimensions, date,
+                      random, unsorted
+  --sort-order <order> asc or desc
+  --slideshow <secs>  Start a slideshow, showing each image this long
+  --filter <glob>     Only show files whose name matches, e.g. \"*.png\", this is
+                      the glob key in config.ini, not its filter key
+  --color-filter <filter> The filter key in config.ini: none, grayscale, sepia,
+                      brightness_contrast, gamma, red, green, blue, alpha
+  --brightness <n>    Brightness for brightness_contrast, 0.0 changes nothing
+  --contrast <n>      Contrast for brightness_contrast, 1.0 changes nothing
+  --gamma <n>         Gamma for the gamma filter, 1.0 changes nothing
+  -h, --help          Print this help
+  -V, --version       Print the version
+";
+
+pub enum Command {
+    Run(Options),
+    Help,
+    Version,
+}
+
+/// What the command line asks for. The config file is read by the caller, from
+/// `config_path`, and the rest is applied on top of it with [`Options::apply`].
+pub struct Options {
+    pub config_path: String,
+    overrides: Vec<Override>,
+    inputs: Vec<String>,
+}
+
+enum Override {
+    Fullscreen,
+    Recursive,
+    Hidden,
+    MaxDepth(usize),
+    Exclude(String),
+    Width(u32),
+    Height(u32),
+    StartAt(String),
+    Sort(SortKey),
+    SortOrder(SortOrder),
+    Filter(String),
+    Slideshow(f32),
+    Title(String),
+    SleepMsPerFrame(u64),
+    CacheMb(u64),
+    ColorFilter(FilterKind),
+    Brightness(f32),
+    Contrast(f32),
+    Gamma(f32),
+    ViewMode(ViewMode),
+}
+
+impl Options {
+    /// Values given on the command line override the ones loaded from the config file.
+    pub fn apply(self, config: &mut Config) {
+        let mut excludes = Vec::new();
+        for option in self.overrides {
+            match option {
+                Override::Fullscreen => config.fullscreen = true,
+                Override::Recursive => config.recursive = true,
+                Override::Hidden => config.show_hidden = true,
+                Override::MaxDepth(depth) => {
+                    config.recursive = true;
+                    config.max_depth = Some(depth);
+                }
+                Override::Exclude(pattern) => excludes.push(pattern),
+                Override::Width(width) => config.window_width = width,
+                Override::Height(height) => config.window_height = height,
+                Override::StartAt(path) => config.start_at = Some(path),
+                Override::Sort(key) => config.sort = key,
+                Override::SortOrder(order) => config.sort_order = order,
+                Override::Filter(glob) => config.glob = Some(glob),
+                Override::Slideshow(interval) => {
+                    config.slideshow = true;
+                    config.slideshow_interval = interval;
+                }
+                Override::Title(title) => config.title = title,
+                Override::SleepMsPerFrame(sleep) => config.sleep_ms_per_frame = sleep,
+                Override::CacheMb(cache_mb) => config.cache_mb = cache_mb,
+                Override::ColorFilter(filter) => config.filter = filter,
+                Override::Brightness(brightness) => config.brightness = brightness,
+                Override::Contrast(contrast) => config.contrast = contrast,
+                Override::Gamma(gamma) => config.gamma = gamma,
+                Override::ViewMode(view_mode) => config.view_mode = view_mode,
+            }
+        }
+        if !excludes.is_empty() {
+            config.exclude = excludes;
+        }
+        config.inputs = if self.inputs.is_empty() {
+            vec!["./".to_string()]
+        } else {
+            self.inputs
+        };
+    }
+}
+
+/// Parses the command line (without the program name).
+pub fn parse(args: &[String]) -> Result<Command, String> {
+    let mut options = Options {
+        config_path: "config.ini".to_string(),
+        overrides: Vec::new(),
+        inputs: Vec::new(),
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            options.inputs.extend(args.by_ref().cloned());
+            break;
+        }
+        let Some(option) = arg.strip_prefix("--").or_else(|| short_option(arg)) else {
+            options.inputs.push(arg.clone());
+            continue;
+        };
+        let (name, inline_value) = match option.split_once('=') {
+            Some((name, value)) => (name, Some(value.to_string())),
+            None => (option, None),
+        };
+        match name {
+            "help" => return Ok(Command::Help),
+            "version" => return Ok(Command::Version),
+            "fullscreen" | "recursive" | "hidden" => {
+                if inline_value.is_some() {
+                    return Err(format!("--{name} doesn't take a value"));
+                }
+                options.overrides.push(match name {
+                    "fullscreen" => Override::Fullscreen,
+                    "recursive" => Override::Recursive,
+                    _ => Override::Hidden,
+                });
+            }
+            "config" | "width" | "height" | "start-at" | "sort" | "sort-order" | "filter"
+            | "input" | "max-depth" | "exclude" | "slideshow" | "title" | "sleep-ms-per-frame"
+            | "cache-mb" | "color-filter" | "brightness" | "contrast" | "gamma" | "view-mode" => {
+                let value = match inline_value {
+                    Some(value) => value,
+                    None => args
+                        .next()
+                        .cloned()
+                        .ok_or_else(|| format!("--{name} needs a value"))?,
+                };
+                match name {
+                    "config" => options.config_path = value,
+                    "input" => options.inputs.push(value),
+                    _ => options.overrides.push(parse_override(name, value)?),
+                }
+            }
+            _ => return Err(format!("Unknown option: {arg}")),
+        }
+    }
+    Ok(Command::Run(options))
+}
+
+fn parse_override(name: &str, value: String) -> Result<Override, String> {
+    Ok(match name {
+        "max-depth" => Override::MaxDepth(parse_number(name, &value)?),
+        "exclude" => Override::Exclude(value),
+        "width" => Override::Width(parse_number(name, &value)?),
+        "height" => Override::Height(parse_number(name, &value)?),
+        "start-at" => Override::StartAt(value),
+        "sort" => Override::Sort(
+            value
+                .parse()
+                .map_err(|_| format!("Unknown sort key: {value}"))?,
+        ),
+        "sort-order" => Override::SortOrder(
+            value
+                .parse()
+                .map_err(|_| format!("Unknown sort order: {value}"))?,
+        ),
+        "filter" => Override::Filter(value),
+        "slideshow" => Override::Slideshow(parse_number(name, &value)?),
+        "title" => Override::Title(value),
+        "sleep-ms-per-frame" => Override::SleepMsPerFrame(parse_number(name, &value)?),
+        "cache-mb" => Override::CacheMb(parse_number(name, &value)?),
+        "color-filter" => Override::ColorFilter(
+            value
+                .parse()
+                .map_err(|_| format!("Unknown filter: {value}"))?,
+        ),
+        "brightness" => Override::Brightness(parse_number(name, &value)?),
+        "contrast" => Override::Contrast(parse_number(name, &value)?),
+        "gamma" => Override::Gamma(parse_number(name, &value)?),
+        "view-mode" => Override::ViewMode(
+            value
+                .parse()
+                .map_err(|_| format!("Unknown view mode: {value}"))?,
+        ),
+        _ => unreachable!("option --{name} is accepted by parse"),
+    })
+}
+
+fn short_option(arg: &str) -> Option<&'static str> {
+    match arg {
+        "-h" => Some("help"),
+        "-V" => Some("version"),
+        _ => None,
+    }
+}
+
+fn parse_number<T: FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("--{name} expects a number, got {value}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(args: &[&str]) -> Result<Config, String> {
+        let args: Vec<String> = args.iter().map(ToString::to_string).collect();
+        match parse(&args)? {
+            Command::Run(options) => {
+                let mut config = Config::default();
+                options.apply(&mut config);
+                Ok(config)
+            }
+            Command::Help => Err("help".to_string()),
+            Command::Version => Err("version".to_string()),
+        }
+    }
+
+    fn options(args: &[&str]) -> Options {
+        let args: Vec<String> = args.iter().map(ToString::to_string).collect();
+        match parse(&args) {
+            Ok(Command::Run(options)) => options,
+            _ => panic!("expected options for {args:?}"),
+        }
+    }
+
+    #[test]
+    fn help_and_version() {
+        for args in [&["--help"][..], &["-h"], &["a.png", "--help", "--bogus"]] {
+            assert_eq!(run(args).unwrap_err(), "help");
+        }
+        for args in [&["--version"][..], &["-V"]] {
+            assert_eq!(run(args).unwrap_err(), "version");
+        }
+    }
+
+    #[test]
+    fn inputs_default_to_the_current_directory() {
+        assert_eq!(run(&[]).unwrap().inputs, ["./"]);
+        let config = run(&["a", "--input", "-b", "--", "--c"]).unwrap();
+        assert_eq!(config.inputs, ["a", "-b", "--c"]);
+    }
+
+    #[test]
+    fn config_path() {
+        assert_eq!(options(&[]).config_path, "config.ini");
+        assert_eq!(options(&["--config", "other.ini"]).config_path, "other.ini");
+        assert_eq!(options(&["--config=other.ini"]).config_path, "other.ini");
+    }
+
+    #[test]
+    fn flags() {
+        let config = run(&["--fullscreen", "--recursive", "--hidden"]).unwrap();
+        assert!(config.fullscreen && config.recursive && config.show_hidden);
+        assert!(run(&["--fullscreen=yes"]).is_err());
+    }
+
+    #[test]
+    fn values() {
+        let config = run(&[
+            "--width",
+            "640",
+            "--height=480",
+            "--start-at",
+            "b.png",
+            "--sort",
+            "modified",
+            "--sort-order",
+            "desc",
+            "--filter",
+            "*.png",
+            "--slideshow",
+            "2.5",
+        ])
+        .unwrap();
+        assert_eq!((config.window_width, config.window_height), (640, 480));
+        assert_eq!(config.start_at.as_deref(), Some("b.png"));
+        assert_eq!(config.sort, SortKey::Modified);
+        assert_eq!(config.sort_order, SortOrder::Descending);
+        assert_eq!(config.glob.as_deref(), Some("*.png"));
+        assert!(config.slideshow);
+        assert!((config.slideshow_interval - 2.5).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn display_values() {
+        let config = run(&[
+            "--title",
+            "Photos",
+            "--sleep-ms-per-frame=10",
+            "--cache-mb",
+            "64",
+            "--color-filter",
+            "brightness_contrast",
+            "--brightness",
+            "0.2",
+            "--contrast=1.5",
+            "--gamma",
+            "2.2",
+            "--view-mode",
+            "actual_size",
+        ])
+        .unwrap();
+        assert_eq!(config.title, "Photos");
+        assert_eq!(config.sleep_ms_per_frame, 10);
+        assert_eq!(config.cache_mb, 64);
+        assert_eq!(config.filter, FilterKind::BrightnessContrast);
+        assert!((config.brightness - 0.2).abs() < f32::EPSILON);
+        assert!((config.contrast - 1.5).abs() < f32::EPSILON);
+        assert!((config.gamma - 2.2).abs() < f32::EPSILON);
+        assert_eq!(config.view_mode, ViewMode::ActualSize);
+    }
+
+    #[test]
+    fn filter_is_the_glob_not_the_color_filter() {
+        let config = run(&["--filter", "*.png"]).unwrap();
+        assert_eq!(config.glob.as_deref(), Some("*.png"));
+        assert_eq!(config.filter, FilterKind::None);
+    }
+
+    #[test]
+    fn max_depth_implies_recursive() {
+        let config = run(&["--max-depth", "2"]).unwrap();
+        assert!(config.recursive);
+        assert_eq!(config.max_depth, Some(2));
+    }
+
+    #[test]
+    fn excludes_replace_the_configured_ones() {
+        let mut config = Config::default();
+        config.exclude = vec!["old".to_string()];
+        options(&["--exclude", "a", "--exclude=b/"]).apply(&mut config);
+        assert_eq!(config.exclude, ["a", "b/"]);
+    }
+
+    #[test]
+    fn bad_arguments() {
+        assert!(run(&["--bogus"]).is_err());
+        assert!(run(&["--width"]).is_err());
+        assert!(run(&["--width", "wide"]).is_err());
+        assert!(run(&["--sort", "colour"]).is_err());
+        assert!(run(&["--sort-order", "up"]).is_err());
+        assert!(run(&["--color-filter", "*.png"]).is_err());
+        assert!(run(&["--view-mode", "zoomed"]).is_err());
+        assert!(run(&["--gamma", "high"]).is_err());
+    }
+}
@@ -1,16 +1,90 @@
 use configparser::ini::Ini;
+use speedy2d::color::Color;
 
 use std::default::Default;
 use std::error::Error;
 
-#[derive(Debug)]
+use strum::IntoEnumIterator;
+
+use crate::app::ScrollAction;
+use crate::bindings::{Action, Bindings};
+use crate::game::camera::ViewMode;
+use crate::game::filters::FilterKind;
+use crate::game::hud::HudPosition;
+use crate::game::sorting::{SortKey, SortOrder};
+use crate::game::transition::TransitionKind;
+
+#[derive(Debug, Clone)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Config {
     path: Option<String>,
     pub title: String,
-    pub input: String,
+    /// The window title while showing an image. `{name}`, `{index}`, `{count}`, `{width}`,
+    /// `{height}`, `{zoom}`, `{dir}` and `{title}` are filled in.
+    pub title_format: String,
+    pub inputs: Vec<String>,
     pub sleep_ms_per_frame: u64,
     pub window_width: u32,
     pub window_height: u32,
+    pub cache_mb: u64,
+    pub filter: FilterKind,
+    pub brightness: f32,
+    pub contrast: f32,
+    pub gamma: f32,
+    pub view_mode: ViewMode,
+    /// Show the info overlay at startup.
+    pub hud: bool,
+    pub hud_position: HudPosition,
+    /// From 0, invisible, to 1, opaque.
+    pub hud_opacity: f32,
+    pub fullscreen: bool,
+    pub background: Color,
+    pub fullscreen_background: Color,
+    /// Show the info overlay in fullscreen at startup.
+    pub fullscreen_hud: bool,
+    pub recursive: bool,
+    /// How many directory levels deep a recursive scan goes, unlimited if unset.
+    pub max_depth: Option<usize>,
+    pub show_hidden: bool,
+    /// Gitignore-style patterns for files and directories to skip.
+    pub exclude: Vec<String>,
+    /// Extensions to show on top of the formats the image decoder supports.
+    pub add_extensions: Vec<String>,
+    /// Extensions never to show, even if the file content is an image.
+    pub remove_extensions: Vec<String>,
+    pub sort: SortKey,
+    pub sort_order: SortOrder,
+    /// Only files with a matching name are shown.
+    pub glob: Option<String>,
+    /// Rotate and flip photos the way their EXIF orientation says.
+    pub auto_orient: bool,
+    /// Move past images that can't be loaded instead of showing why.
+    pub skip_broken: bool,
+    /// Pick up files that are added, removed or changed while running.
+    pub watch: bool,
+    /// Start with the slideshow running.
+    pub slideshow: bool,
+    /// Seconds each image is shown for in the slideshow.
+    pub slideshow_interval: f32,
+    /// Start over after the last image instead of stopping.
+    pub slideshow_loop: bool,
+    pub slideshow_shuffle: bool,
+    /// The largest side of gallery thumbnails, in pixels.
+    pub thumbnail_size: u32,
+    /// How much memory gallery thumbnails may take, in MB.
+    pub thumbnail_mb: u64,
+    /// Keep thumbnails in ~/.cache/thumbnails, where other programs find them too.
+    pub thumbnail_cache: bool,
+    /// How the view changes from one image to the next.
+    pub transition: TransitionKind,
+    /// Seconds a transition takes.
+    pub transition_duration: f32,
+    pub wheel_scroll: ScrollAction,
+    pub trackpad_scroll: ScrollAction,
+    /// From the `[keys]` section, each action set there replaces its default bindings.
+    pub keys: Bindings,
+    /// The file to open first, only set from the command line.
+    pub start_at: Option<String>,
 }
 
 impl Config {
@@ -25,6 +99,7 @@ impl Config {
         config
     }
 
+    #[allow(clippy::cast_possible_truncation, clippy::too_many_lines)]
     pub fn reload(&mut self) -> Result<(), Box<dyn Error>> {
         let mut ini = Ini::new();
         match &self.path {
@@ -32,7 +107,7 @@ impl Config {
                 if ini.load(path).is_ok() {
                     let default_section = "default";
                     if let Some(input) = ini.get(default_section, "input") {
-                        self.input = input;
+                        self.inputs = vec![input];
                     }
                     if let Some(title) = ini.get(default_section, "title") {
                         self.title = title;
@@ -46,6 +121,127 @@ impl Config {
                     if let Some(height) = ini.getuint(default_section, "window_height")? {
                         self.window_height = height.try_into()?;
                     }
+                    if let Some(cache_mb) = ini.getuint(default_section, "cache_mb")? {
+                        self.cache_mb = cache_mb;
+                    }
+                    if let Some(filter) = ini.get(default_section, "filter") {
+                        self.filter = filter.parse()?;
+                    }
+                    if let Some(brightness) = ini.getfloat(default_section, "brightness")? {
+                        self.brightness = brightness as f32;
+                    }
+                    if let Some(contrast) = ini.getfloat(default_section, "contrast")? {
+                        self.contrast = contrast as f32;
+                    }
+                    if let Some(gamma) = ini.getfloat(default_section, "gamma")? {
+                        self.gamma = gamma as f32;
+                    }
+                    if let Some(view_mode) = ini.get(default_section, "view_mode") {
+                        self.view_mode = view_mode.parse()?;
+                    }
+                    if let Some(fullscreen) = ini.getbool(default_section, "fullscreen")? {
+                        self.fullscreen = fullscreen;
+                    }
+                    if let Some(recursive) = ini.getbool(default_section, "recursive")? {
+                        self.recursive = recursive;
+                    }
+                    if let Some(max_depth) = ini.getuint(default_section, "max_depth")? {
+                        self.max_depth = Some(max_depth.try_into()?);
+                    }
+                    if let Some(show_hidden) = ini.getbool(default_section, "show_hidden")? {
+                        self.show_hidden = show_hidden;
+                    }
+                    if let Some(exclude) = ini.get(default_section, "exclude") {
+                        self.exclude = split_list(&exclude);
+                    }
+                    if let Some(extensions) = ini.get(default_section, "add_extensions") {
+                        self.add_extensions = split_list(&extensions);
+                    }
+                    if let Some(extensions) = ini.get(default_section, "remove_extensions") {
+                        self.remove_extensions = split_list(&extensions);
+                    }
+                    if let Some(sort) = ini.get(default_section, "sort") {
+                        self.sort = sort.parse()?;
+                    }
+                    if let Some(sort_order) = ini.get(default_section, "sort_order") {
+                        self.sort_order = sort_order.parse()?;
+                    }
+                    if let Some(title_format) = ini.get(default_section, "title_format") {
+                        self.title_format = title_format;
+                    }
+                    if let Some(background) = ini.get(default_section, "background") {
+                        self.background = parse_color(&background)?;
+                    }
+                    if let Some(background) = ini.get(default_section, "fullscreen_background") {
+                        self.fullscreen_background = parse_color(&background)?;
+                    }
+                    if let Some(hud) = ini.getbool(default_section, "fullscreen_hud")? {
+                        self.fullscreen_hud = hud;
+                    }
+                    if let Some(hud) = ini.getbool(default_section, "hud")? {
+                        self.hud = hud;
+                    }
+                    if let Some(hud_position) = ini.get(default_section, "hud_position") {
+                        self.hud_position = hud_position.parse()?;
+                    }
+                    if let Some(hud_opacity) = ini.getfloat(default_section, "hud_opacity")? {
+                        self.hud_opacity = hud_opacity as f32;
+                    }
+                    if let Some(auto_orient) = ini.getbool(default_section, "auto_orient")? {
+                        self.auto_orient = auto_orient;
+                    }
+                    if let Some(skip_broken) = ini.getbool(default_section, "skip_broken")? {
+                        self.skip_broken = skip_broken;
+                    }
+                    if let Some(watch) = ini.getbool(default_section, "watch")? {
+                        self.watch = watch;
+                    }
+                    if let Some(slideshow) = ini.getbool(default_section, "slideshow")? {
+                        self.slideshow = slideshow;
+                    }
+                    if let Some(interval) = ini.getfloat(default_section, "slideshow_interval")? {
+                        self.slideshow_interval = interval as f32;
+                    }
+                    if let Some(looping) = ini.getbool(default_section, "slideshow_loop")? {
+                        self.slideshow_loop = looping;
+                    }
+                    if let Some(shuffle) = ini.getbool(default_section, "slideshow_shuffle")? {
+                        self.slideshow_shuffle = shuffle;
+                    }
+                    if let Some(size) = ini.getuint(default_section, "thumbnail_size")? {
+                        self.thumbnail_size = size.try_into()?;
+                    }
+                    if let Some(thumbnail_mb) = ini.getuint(default_section, "thumbnail_mb")? {
+                        self.thumbnail_mb = thumbnail_mb;
+                    }
+                    if let Some(cache) = ini.getbool(default_section, "thumbnail_cache")? {
+                        self.thumbnail_cache = cache;
+                    }
+                    if let Some(transition) = ini.get(default_section, "transition") {
+                        self.transition = transition.parse()?;
+                    }
+                    if let Some(duration) = ini.getfloat(default_section, "transition_duration")? {
+                        self.transition_duration = duration as f32;
+                    }
+                    if let Some(wheel_scroll) = ini.get(default_section, "wheel_scroll") {
+                        self.wheel_scroll = wheel_scroll.parse()?;
+                    }
+                    if let Some(trackpad_scroll) = ini.get(default_section, "trackpad_scroll") {
+                        self.trackpad_scroll = trackpad_scroll.parse()?;
+                    }
+                    if let Some(glob) = ini.get(default_section, "glob") {
+                        self.glob = Some(glob);
+                    }
+
+                    // Bad bindings leave the ones in use alone.
+                    let mut keys = self.keys.clone();
+                    for action in Action::iter() {
+                        if let Some(bound) = ini.get("keys", &action.to_string()) {
+                            keys.set(action, &bound)?;
+                        }
+                    }
+                    keys.validate()?;
+                    self.keys = keys;
                 }
             }
             None => return Err("Tried to reload config with no set path!".into()),
@@ -58,11 +254,68 @@ impl Default for Config {
     fn default() -> Self {
         Self {
             path: None,
-            input: "assets/folder".to_string(),
+            inputs: vec!["assets/folder".to_string()],
             title: "pfiew".to_string(),
+            title_format: "{name} ({index}/{count}) - {title}".to_string(),
             sleep_ms_per_frame: 5,
             window_width: 640,
             window_height: 640,
+            cache_mb: 512,
+            filter: FilterKind::None,
+            brightness: 0.0,
+            contrast: 1.0,
+            gamma: 1.0,
+            view_mode: ViewMode::Fit,
+            fullscreen: false,
+            background: Color::from_gray(0.3),
+            fullscreen_background: Color::BLACK,
+            fullscreen_hud: false,
+            recursive: false,
+            max_depth: None,
+            show_hidden: false,
+            exclude: Vec::new(),
+            add_extensions: Vec::new(),
+            remove_extensions: Vec::new(),
+            sort: SortKey::Name,
+            sort_order: SortOrder::Ascending,
+            glob: None,
+            hud: false,
+            hud_position: HudPosition::TopLeft,
+            hud_opacity: 0.8,
+            auto_orient: true,
+            skip_broken: false,
+            watch: true,
+            slideshow: false,
+            slideshow_interval: 5.0,
+            slideshow_loop: true,
+            slideshow_shuffle: false,
+            thumbnail_size: 128,
+            thumbnail_mb: 128,
+            thumbnail_cache: true,
+            transition: TransitionKind::None,
+            transition_duration: 0.25,
+            wheel_scroll: ScrollAction::Zoom,
+            trackpad_scroll: ScrollAction::Pan,
+            keys: Bindings::default(),
+            start_at: None,
         }
     }
 }
+
+/// Parses colors written like `#4d4d4d` or `4d4d4d`.
+fn parse_color(text: &str) -> Result<Color, String> {
+    let hex = text.trim().trim_start_matches('#');
+    match u32::from_str_radix(hex, 16) {
+        Ok(rgb) if hex.len() == 6 => Ok(Color::from_hex_rgb(rgb)),
+        _ => Err(format!("Expected a color like #4d4d4d, got {text:?}")),
+    }
+}
+
+/// A comma separated ini value, without the blanks.
+fn split_list(text: &str) -> Vec<String> {
+    text.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(ToString::to_string)
+        .collect()
+}
@@ -1,80 +1,172 @@
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
 use glam::{UVec2, Vec2};
-use speedy2d::window::VirtualKeyCode;
-use speedy2d::{color::Color, Graphics2D, Rect};
-use walkdir::WalkDir;
+use speedy2d::image::ImageHandle;
+use speedy2d::Graphics2D;
+use strum::IntoEnumIterator;
 
 use crate::app::{Keyboard, Mouse};
+use crate::bindings::Action;
 use crate::config::Config;
 
-mod camera;
-use crate::game::camera::Camera;
+pub mod camera;
+use crate::game::camera::{Camera, Orientation, ViewMode};
 
 mod world;
-use crate::game::world::World;
+use crate::game::world::{EntityId, LoadState, World};
 
 mod task_manager;
 use crate::game::task_manager::TaskManager;
 
+mod task_queue;
+use crate::game::task_queue::Priority;
+
+pub mod filters;
+use crate::game::filters::{Filter, FilterKind, FilterParams};
+
+mod animation;
+use crate::game::animation::Playback;
+
+pub mod scanner;
+use crate::game::scanner::FileTypes;
+
+mod watcher;
+use crate::game::watcher::{WatchEvent, Watcher};
+
+pub mod hud;
+use crate::game::hud::Hud;
+
+pub mod sorting;
+use crate::game::sorting::{SortKey, SortOrder};
+
+pub mod slideshow;
+use crate::game::slideshow::Slideshow;
+
+pub mod transition;
+use crate::game::transition::Transition;
+
+mod gallery;
+use crate::game::gallery::Gallery;
+
+mod thumbnail_cache;
+
+/// How far the view moves per second while a pan key is held, in screen pixels.
+const KEY_PAN_SPEED: f32 = 600.0;
+/// How far the image turns per second while a fine rotation key is held, in degrees.
+const KEY_ROTATE_SPEED: f32 = 60.0;
+/// How much one press of a zoom key zooms, in mouse wheel lines.
+const KEY_ZOOM_LINES: f64 = 4.0;
+
+/// Changes to the window, which only the app can make.
+pub enum WindowRequest {
+    SetTitle(String),
+    ToggleFullscreen,
+    Quit,
+}
+
 pub struct Game {
     config: Config,
+    /// Which files the watcher finds are images.
+    file_types: FileTypes,
     world: World,
     task_manager: TaskManager,
 
     selected: usize,
+    /// The file to select once the scan finds it.
+    start_at: Option<PathBuf>,
+    watcher: Option<Watcher>,
+    sort: SortKey,
+    sort_order: SortOrder,
+    /// Sort once the scan is done, the scan itself only orders by name.
+    sort_pending: bool,
+    playback: Playback,
+    slideshow: Slideshow,
+    transition: Transition,
+    gallery: Gallery,
     camera: Camera,
+    hud: Hud,
+    /// The last title asked for, so it is only set again when it changes.
+    title: String,
+    window_requests: Vec<WindowRequest>,
+    /// The image and viewport size the camera was last fitted to.
+    fitted: Option<(EntityId, UVec2)>,
+    /// Which way the selection last moved, so broken images are skipped the same way.
+    forward: bool,
+    /// The first broken image skipped, to stop going around once every image is broken.
+    skipped_from: Option<EntityId>,
     mouse: Mouse,
+    /// When input was last handled, so held keys move the view at the same speed
+    /// whatever the frame rate.
+    last_input: Instant,
 
     counter: usize,
     viewport_size: UVec2,
     inverted: bool,
+    filter: FilterKind,
 }
 
 impl Game {
     pub fn new(config: Config) -> Self {
-        let supported_extensions = vec![
-            "png", "jpg", "jpeg", "gif", "bmp", "ico", "tiff", "tif", "webp", "avif", "pbm", "pgm",
-            "ppm", "pnm", "dds", "tga", "ff",
-        ];
-
-        let mut world = World::new();
+        let megabytes =
+            |mb: u64| usize::try_from(mb.saturating_mul(1024 * 1024)).unwrap_or(usize::MAX);
+        let world = World::new(megabytes(config.cache_mb), megabytes(config.thumbnail_mb));
         let viewport_size = UVec2::new(config.window_width, config.window_height);
-        let paths: Vec<&str> = vec![&config.input];
-        println!("Reading {} asset paths:", paths.len());
-        for path in paths {
-            let mut count = 0;
-            print!("In {path}: ");
-            for entry in WalkDir::new(path)
-                .follow_links(true)
-                .max_depth(1)
-                .sort_by_file_name()
-                .into_iter()
-                .filter_map(Result::ok)
-            {
-                if entry.file_type().is_file() {
-                    if let Some(ext) = entry.path().extension() {
-                        if supported_extensions.contains(&ext.to_string_lossy().as_ref()) {
-                            count += 1;
-                            world.spawn_asset(entry.into_path());
-                        }
-                    }
+        let start_at = config
+            .start_at
+            .as_deref()
+            .map(Path::new)
+            .or_else(|| scanner::opened_file(&config))
+            .map(Path::to_path_buf);
+        let mut task_manager = TaskManager::new(4, config.auto_orient, config.thumbnail_cache);
+        task_manager.scan(config.clone());
+
+        let mut watcher = if config.watch { Watcher::new() } else { None };
+        if let Some(watcher) = &mut watcher {
+            for input in &config.inputs {
+                let input = Path::new(input);
+                if input.is_dir() {
+                    watcher.watch(input);
+                } else {
+                    watcher.watch(watcher::watched_dir(input));
                 }
             }
-            println!("{count} assets.");
-        }
-
-        if world.len() == 0 {
-            println!("No images to display, exiting");
-            std::process::exit(0);
         }
 
+        let camera = Camera::new(config.view_mode);
+        let hud = Hud::new(&config);
+        let slideshow = Slideshow::new(&config);
+        let transition = Transition::new(&config);
+        let gallery = Gallery::new(&config);
+        let file_types = FileTypes::new(&config);
+        let (sort, sort_order) = (config.sort, config.sort_order);
+        let sort_pending = !sort.is_scan_order(sort_order);
         Self {
+            filter: config.filter,
             config,
+            file_types,
             world,
-            task_manager: TaskManager::new(4),
+            task_manager,
             selected: 0,
-            camera: Camera::new(),
+            start_at,
+            watcher,
+            sort,
+            sort_order,
+            sort_pending,
+            playback: Playback::new(),
+            slideshow,
+            transition,
+            gallery,
+            camera,
+            hud,
+            title: String::new(),
+            window_requests: Vec::new(),
+            fitted: None,
+            forward: true,
+            skipped_from: None,
 
             mouse: Mouse::new(),
+            last_input: Instant::now(),
 
             counter: 0,
             viewport_size,
@@ -88,75 +180,765 @@ impl Game {
         self.viewport_size = viewport_size;
         let mouse_delta = mouse.position - self.mouse.position;
         let scroll_delta = mouse.scroll_accumulated - self.mouse.scroll_accumulated;
+        let scroll_pan = mouse.scroll_pan - self.mouse.scroll_pan;
         self.mouse = mouse.clone();
 
+        let keys = &self.config.keys;
+        let held = |action| f32::from(u8::from(keys.held(action, keyboard, mouse)));
+        let pan = keys.held(Action::Pan, keyboard, mouse);
+        let zoom = keys.wheel(Action::ZoomIn, keyboard, scroll_delta)
+            - keys.wheel(Action::ZoomOut, keyboard, scroll_delta);
+        let rotation = held(Action::RotateRightFine) - held(Action::RotateLeftFine);
+        let pan_direction = Vec2::new(
+            held(Action::PanLeft) - held(Action::PanRight),
+            held(Action::PanUp) - held(Action::PanDown),
+        );
+        let actions = keys.triggered(keyboard, mouse, scroll_delta);
+        if self.gallery.open {
+            self.last_input = Instant::now();
+            self.gallery_input(keyboard, mouse, scroll_delta, scroll_pan, actions);
+            return;
+        }
+
         self.camera
-            .handle_input(mouse, mouse_delta, scroll_delta, keyboard);
-        if keyboard.just_pressed.contains(&VirtualKeyCode::E) {
-            self.selected += 1;
-            if self.selected >= self.world.len() {
-                self.selected = 0;
+            .handle_input(mouse.position, mouse_delta, pan, zoom);
+        if scroll_pan != Vec2::ZERO {
+            self.camera.pan(scroll_pan);
+        }
+        let now = Instant::now();
+        // Give the user a full interval to look at what they just did.
+        let moved = pan && mouse_delta != Vec2::ZERO;
+        if moved
+            || zoom != 0.0
+            || rotation != 0.0
+            || scroll_pan != Vec2::ZERO
+            || pan_direction != Vec2::ZERO
+            || !actions.is_empty()
+        {
+            self.slideshow.delay(now);
+        }
+        // Held keys move by time, not by frame, so they don't go faster on a faster screen.
+        let elapsed = now.duration_since(self.last_input).as_secs_f32().min(0.1);
+        if pan_direction != Vec2::ZERO {
+            let speed = if keyboard.modifiers.shift() {
+                KEY_PAN_SPEED * 4.0
+            } else {
+                KEY_PAN_SPEED
+            };
+            self.camera.pan(pan_direction.normalize() * speed * elapsed);
+        }
+        self.last_input = now;
+        if rotation != 0.0 {
+            self.orient(|orientation| orientation.rotate(rotation * KEY_ROTATE_SPEED * elapsed));
+        }
+        for action in actions {
+            self.perform(action);
+        }
+    }
+
+    /// Scrolls the gallery, moves the selection through it and opens the image picked.
+    fn gallery_input(
+        &mut self,
+        keyboard: &Keyboard,
+        mouse: &Mouse,
+        scroll_delta: f64,
+        scroll_pan: Vec2,
+        actions: Vec<Action>,
+    ) {
+        let count = self.world.len();
+        let viewport_size = self.viewport_size;
+        self.gallery
+            .scroll_lines(scroll_delta, viewport_size, count);
+        self.gallery.scroll_by(-scroll_pan.y, viewport_size, count);
+
+        let keys = &self.config.keys;
+        let pressed = |action| keys.pressed(action, keyboard, mouse);
+        let columns = self.gallery.columns(viewport_size);
+        let mut selected = self.selected;
+        if pressed(Action::PanLeft) || actions.contains(&Action::Prev) {
+            selected = selected.saturating_sub(1);
+        }
+        if pressed(Action::PanRight) || actions.contains(&Action::Next) {
+            selected += 1;
+        }
+        if pressed(Action::PanUp) && selected >= columns {
+            selected -= columns;
+        }
+        if pressed(Action::PanDown) && selected + columns < count {
+            selected += columns;
+        }
+        let clicked = if pressed(Action::Pan) {
+            self.gallery.index_at(mouse.position, viewport_size, count)
+        } else {
+            None
+        };
+        if let Some(index) = clicked {
+            selected = index;
+            self.gallery.open = false;
+        }
+        selected = selected.min(count.saturating_sub(1));
+        if selected != self.selected {
+            self.start_at = None;
+            self.selected = selected;
+            self.gallery.reveal(selected, viewport_size, count);
+        }
+
+        for action in actions {
+            match action {
+                Action::ToggleGallery | Action::Open | Action::Quit => self.gallery.open = false,
+                Action::NextDir | Action::PrevDir => {
+                    self.perform(action);
+                    self.gallery.reveal(self.selected, viewport_size, count);
+                }
+                Action::ToggleFullscreen
+                | Action::ToggleHud
+                | Action::ToggleSlideshow
+                | Action::Invert
+                | Action::CycleFilter
+                | Action::CycleSort
+                | Action::ReverseSort => self.perform(action),
+                _ => {}
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_lines)]
+    fn perform(&mut self, action: Action) {
+        let frame_count = self.selected_id().and_then(|id| self.world.frame_count(id));
+        match action {
+            Action::Next => {
+                self.start_at = None;
+                self.forward = true;
+                self.selected += 1;
+                if self.selected >= self.world.len() {
+                    self.selected = 0;
+                }
+                println!("Selecting image {}", self.selected);
+            }
+            Action::Prev => {
+                self.start_at = None;
+                self.forward = false;
+                if self.selected == 0 {
+                    self.selected = self.world.len();
+                }
+                // no images in folder
+                if self.selected == 0 {
+                    return;
+                }
+                self.selected -= 1;
+                println!("Selecting image {}", self.selected);
+            }
+            Action::NextDir => self.jump_directory(true),
+            Action::PrevDir => self.jump_directory(false),
+            Action::Quit => self.window_requests.push(WindowRequest::Quit),
+            Action::ToggleHud => self.hud.toggle(),
+            Action::ToggleGallery => {
+                self.gallery.open = !self.gallery.open;
+                let count = self.world.len();
+                self.gallery
+                    .reveal(self.selected, self.viewport_size, count);
+            }
+            Action::ToggleSlideshow => {
+                self.slideshow.toggle();
+                println!("Slideshow: {}", self.slideshow.running);
+            }
+            Action::ToggleFullscreen => self.window_requests.push(WindowRequest::ToggleFullscreen),
+            Action::Invert => {
+                self.inverted = !self.inverted;
+                println!("Inverted: {}", self.inverted);
+            }
+            Action::CycleViewMode => {
+                self.camera.mode = ViewMode::iter()
+                    .cycle()
+                    .skip_while(|mode| *mode != self.camera.mode)
+                    .nth(1)
+                    .unwrap_or(ViewMode::Fit);
+                self.fitted = None;
+                println!("View mode: {}", self.camera.mode);
+            }
+            Action::CycleFilter => {
+                self.filter = FilterKind::iter()
+                    .cycle()
+                    .skip_while(|kind| *kind != self.filter)
+                    .nth(1)
+                    .unwrap_or(FilterKind::None);
+                println!("Filter: {}", self.filter);
+            }
+            Action::CycleSort | Action::ReverseSort => {
+                if action == Action::ReverseSort {
+                    self.sort_order = self.sort_order.reversed();
+                } else {
+                    self.sort = SortKey::iter()
+                        .cycle()
+                        .skip_while(|key| *key != self.sort)
+                        .nth(1)
+                        .unwrap_or(SortKey::Name);
+                }
+                println!("Sorting by {} ({})", self.sort, self.sort_order);
+                self.sort_pending = true;
+            }
+            Action::RotateLeft => self.orient(|orientation| orientation.rotate(-90.0)),
+            Action::RotateRight => self.orient(|orientation| orientation.rotate(90.0)),
+            Action::FlipHorizontal => self.orient(Orientation::flip_horizontal),
+            Action::FlipVertical => self.orient(Orientation::flip_vertical),
+            Action::TogglePause => {
+                self.playback.toggle_pause();
+                println!("Animation paused: {}", self.playback.paused);
+            }
+            Action::NextFrame | Action::PrevFrame => {
+                self.playback.step(frame_count, action == Action::NextFrame);
+                if let Some(label) = self.selected_id().and_then(|id| self.frame_label(id)) {
+                    println!("{label}");
+                }
+            }
+            Action::Faster | Action::Slower => {
+                self.playback.change_speed(action == Action::Faster);
+                println!("Animation speed: {}x", self.playback.speed);
+            }
+            Action::ZoomIn | Action::ZoomOut => {
+                let lines = if action == Action::ZoomIn {
+                    KEY_ZOOM_LINES
+                } else {
+                    -KEY_ZOOM_LINES
+                };
+                let center = self.viewport_size.as_vec2() / 2.0;
+                self.camera.zoom(center, lines);
+            }
+            Action::ResetZoom => {
+                self.camera.manual = false;
+                self.fitted = None;
+            }
+            Action::Open
+            | Action::Pan
+            | Action::PanLeft
+            | Action::PanRight
+            | Action::PanUp
+            | Action::PanDown
+            | Action::RotateLeftFine
+            | Action::RotateRightFine => {}
+        }
+    }
+
+    /// Called by the app once the window entered or left fullscreen.
+    pub const fn set_fullscreen(&mut self, fullscreen: bool) {
+        self.hud.set_fullscreen(fullscreen);
+        // The view made for the old window size rarely suits the new one.
+        self.camera.manual = false;
+        self.fitted = None;
+    }
+
+    fn selected_id(&self) -> Option<EntityId> {
+        self.world.id_at(self.selected)
+    }
+
+    /// Applies finished tasks, staying on the same image when the list gets sorted.
+    fn apply_results(&mut self, graphics: &mut Graphics2D) {
+        let selected_id = self.selected_id();
+        let found = self.task_manager.update(&mut self.world, graphics);
+        if let Some(index) = selected_id.and_then(|id| self.world.index_of(id)) {
+            self.selected = index;
+        }
+        self.follow_scan(&found);
+    }
+
+    /// Selects `start_at` once the scan finds it, watches the directories of new images,
+    /// and exits if the scan found nothing at all.
+    fn follow_scan(&mut self, found: &[EntityId]) {
+        if let Some(start_at) = &self.start_at {
+            let start = found.iter().find(|id| {
+                self.world
+                    .get_path(**id)
+                    .is_some_and(|path| scanner::is_start(path, start_at))
+            });
+            if let Some(index) = start.and_then(|id| self.world.index_of(*id)) {
+                self.selected = index;
+                self.start_at = None;
             }
-            println!("Selecting image {}", self.selected);
         }
-        if keyboard.just_pressed.contains(&VirtualKeyCode::Q) {
-            if self.selected == 0 {
-                self.selected = self.world.len();
+        if let Some(watcher) = &mut self.watcher {
+            for id in found {
+                if let Some(path) = self.world.get_path(*id) {
+                    watcher.watch(watcher::watched_dir(path));
+                }
             }
-            // no images in folder
-            if self.selected == 0 {
+        }
+
+        // Only right after the scan, files removed later leave an empty list instead.
+        if self.task_manager.take_scan_finished() && self.world.len() == 0 {
+            println!("No images to display, exiting");
+            std::process::exit(0);
+        }
+        if self.sort_pending && !self.task_manager.is_scanning() && !self.task_manager.is_sorting()
+        {
+            self.sort_pending = false;
+            self.task_manager
+                .sort(self.world.entries(), self.sort, self.sort_order);
+        }
+    }
+
+    /// Keeps the list in sync with the file system, while staying on the same image.
+    fn apply_file_changes(&mut self) {
+        let Some(watcher) = &mut self.watcher else {
+            return;
+        };
+        let events = watcher.poll();
+        if events.is_empty() {
+            return;
+        }
+        let selected_id = self.selected_id();
+        let mut inserted = false;
+        for event in events {
+            match event {
+                WatchEvent::Changed(path) => match self.world.find_path(&path) {
+                    Some(id) => {
+                        println!("Reloading {}", path.display());
+                        self.world.drop_image(id);
+                        self.world.drop_thumbnail(id);
+                    }
+                    // A file that doesn't look like an image yet may still be being written,
+                    // it is looked at again when it is next closed after writing.
+                    None => {
+                        if scanner::accepts(&self.config, &self.file_types, &path) {
+                            println!("Found {}", path.display());
+                            inserted |= self.world.insert_asset(path).is_some();
+                        }
+                    }
+                },
+                WatchEvent::Removed(path) => {
+                    if let Some(id) = self.world.find_path(&path) {
+                        println!("Removed {}", path.display());
+                        self.world.remove_asset(id);
+                    }
+                }
+                WatchEvent::Renamed { from, to } => match self.world.find_path(&from) {
+                    Some(id) if scanner::accepts(&self.config, &self.file_types, &to) => {
+                        println!("Renamed {} to {}", from.display(), to.display());
+                        self.world.rename_asset(id, to);
+                    }
+                    Some(id) => self.world.remove_asset(id),
+                    // Editors save by renaming a temporary file over the image.
+                    None => match self.world.find_path(&to) {
+                        Some(id) => {
+                            println!("Reloading {}", to.display());
+                            self.world.drop_image(id);
+                            self.world.drop_thumbnail(id);
+                        }
+                        None => {
+                            if scanner::accepts(&self.config, &self.file_types, &to) {
+                                inserted |= self.world.insert_asset(to).is_some();
+                            }
+                        }
+                    },
+                },
+            }
+        }
+
+        // New images are placed by name, anything else needs another pass.
+        if inserted && self.sort != SortKey::Random && !self.sort.is_scan_order(self.sort_order) {
+            self.sort_pending = true;
+        }
+
+        // A removed image is replaced by the one after it.
+        self.selected = selected_id
+            .and_then(|id| self.world.index_of(id))
+            .unwrap_or_else(|| self.selected.min(self.world.len().saturating_sub(1)));
+    }
+
+    /// Turns or mirrors the selected image.
+    fn orient(&mut self, change: impl FnOnce(&mut Orientation)) {
+        let Some(id) = self.selected_id() else {
+            return;
+        };
+        let mut orientation = self.world.get_orientation(id);
+        change(&mut orientation);
+        self.world.set_orientation(id, orientation);
+        self.camera.orientation = orientation;
+        // Refit to the turned image, unless the view was zoomed or panned by hand.
+        if !self.camera.manual {
+            self.fitted = None;
+        }
+    }
+
+    /// Selects the first image of the next or previous directory, wrapping around.
+    fn jump_directory(&mut self, forward: bool) {
+        self.forward = forward;
+        let len = self.world.len();
+        if len == 0 {
+            return;
+        }
+        let dir = |index: usize| {
+            self.world
+                .id_at(index)
+                .and_then(|id| self.world.get_path(id))
+                .and_then(|path| path.parent())
+        };
+        let group_start = |mut index: usize| {
+            while index > 0 && dir(index - 1) == dir(index) {
+                index -= 1;
+            }
+            index
+        };
+
+        let current = group_start(self.selected);
+        let target = if forward {
+            (current..len)
+                .find(|index| dir(*index) != dir(current))
+                .unwrap_or(0)
+        } else if current == 0 {
+            group_start(len - 1)
+        } else {
+            group_start(current - 1)
+        };
+        self.start_at = None;
+        if target != self.selected {
+            self.selected = target;
+            if let Some(dir) = dir(target) {
+                println!("Selecting image {} in {}", self.selected, dir.display());
+            }
+        }
+    }
+
+    /// The filter pipeline every displayed image should currently be run through.
+    fn filters(&self) -> Vec<Filter> {
+        let params = FilterParams {
+            brightness: self.config.brightness,
+            contrast: self.config.contrast,
+            gamma: self.config.gamma,
+        };
+        let mut filters: Vec<Filter> = self.filter.filter(params).into_iter().collect();
+        if self.inverted {
+            filters.push(Filter::Invert);
+        }
+        filters
+    }
+
+    /// Re-applies the view mode when the selection or the viewport changes,
+    /// unless the user has moved the camera on the current image.
+    fn fit_camera(&mut self) {
+        let Some(id) = self.selected_id() else {
+            return;
+        };
+        let Some(image) = self.world.get_image(id) else {
+            return;
+        };
+        let current = (id, self.viewport_size);
+        if self.fitted == Some(current) {
+            return;
+        }
+        let selection_changed = self.fitted.map(|(id, _)| id) != Some(id);
+        if selection_changed || !self.camera.manual {
+            let size = image.size().as_vec2();
+            self.camera.fit(size, self.viewport_size.as_vec2());
+        }
+        self.fitted = Some(current);
+    }
+
+    /// Loads the image, or re-filters the cached pixels if the filters changed since.
+    /// Animations don't keep their unfiltered pixels, so they get decoded again.
+    fn request_image(&mut self, id: EntityId, filters: &[Filter], priority: Priority) {
+        if let LoadState::Failed(_) = self.world.load_state(id) {
+            return;
+        }
+        if let Some(image) = self.world.get_image(id) {
+            if self.world.get_filters(id) == Some(filters) {
+                return;
+            }
+            let size = *image.size();
+            if let Some(pixels) = self.world.get_pixels(id) {
+                self.task_manager
+                    .apply_filters(id, pixels.clone(), size, filters, priority);
                 return;
             }
-            self.selected -= 1;
-            println!("Selecting image {}", self.selected);
         }
-        if keyboard.just_pressed.contains(&VirtualKeyCode::R) {
-            self.inverted = !self.inverted;
-            println!("Inverted: {}", self.inverted);
+        if let Some(path) = self.world.get_path(id) {
+            self.task_manager
+                .load(id, path.clone(), filters, 0, priority);
+        }
+    }
+
+    /// Decodes the frames of the selected animation coming up next that aren't kept.
+    fn request_frames(&mut self, id: EntityId, filters: &[Filter]) {
+        if self.world.get_filters(id) != Some(filters) {
+            return;
+        }
+        let Some(first_frame) = self.world.missing_frame(id, self.playback.frame) else {
+            return;
+        };
+        if let Some(path) = self.world.get_path(id) {
+            self.task_manager
+                .load_frames(id, path.clone(), filters, first_frame);
         }
     }
 
     pub fn update(&mut self, graphics: &mut Graphics2D, _current_frame: u64) {
-        let mut preload_ids = vec![self.selected];
+        let now = Instant::now();
+        self.apply_file_changes();
+        self.advance_slideshow(now);
+
+        let mut preload = vec![self.selected];
+        // The slideshow only moves on once its next image is loaded.
+        if let Some(upcoming) = self.slideshow.upcoming(self.selected, self.world.order()) {
+            preload.push(upcoming);
+        }
 
         if self.selected > 0 {
-            preload_ids.push(self.selected - 1);
+            preload.push(self.selected - 1);
         }
         if self.selected + 1 < self.world.len() {
-            preload_ids.push(self.selected + 1);
+            preload.push(self.selected + 1);
         }
         if self.selected + 2 < self.world.len() {
-            preload_ids.push(self.selected + 2);
+            preload.push(self.selected + 2);
         }
+        let preload_ids: Vec<EntityId> = preload
+            .into_iter()
+            .filter_map(|index| self.world.id_at(index))
+            .collect();
 
-        // Request loading if needed
-        for id in preload_ids {
-            if self.world.get_image(id).is_none() {
-                if let Some(path) = self.world.get_path(id) {
-                    self.task_manager.load(id, path.clone());
-                }
-            }
+        // Request loading if needed, the selected image before its neighbors
+        let filters = self.filters();
+        let visible = self.selected_id();
+        for &id in &preload_ids {
+            let priority = if Some(id) == visible {
+                Priority::Visible
+            } else {
+                Priority::Neighbor
+            };
+            self.request_image(id, &filters, priority);
         }
+        let thumbnails = if self.gallery.open {
+            self.request_thumbnails()
+        } else {
+            Vec::new()
+        };
+        // Forget about images that were skipped past before they got their turn
+        self.task_manager
+            .prioritize(visible, &preload_ids, &thumbnails);
 
         // Apply completed tasks
-        self.task_manager.update(&mut self.world, graphics);
+        self.apply_results(graphics);
+        self.world.evict_thumbnails(&thumbnails);
 
-        if self.world.get_image(self.selected).is_none() {
-            if let Some(path) = self.world.get_path(self.selected) {
-                self.task_manager.load(self.selected, path.clone());
+        let Some(selected_id) = self.selected_id() else {
+            return;
+        };
+        self.request_image(selected_id, &filters, Priority::Visible);
+        if self.task_manager.is_idle() {
+            if let Some(next_id) = self.world.id_at(self.selected + 1) {
+                self.request_image(next_id, &filters, Priority::Neighbor);
             }
         }
-        let next_selected = self.selected + 1;
-        if self.task_manager.is_idle()
-            && next_selected < self.world.len()
-            && self.world.get_image(next_selected).is_none()
-        {
-            if let Some(path) = self.world.get_path(next_selected) {
-                self.task_manager.load(next_selected, path.clone());
+
+        self.apply_results(graphics);
+        if self.skip_broken(selected_id) {
+            return;
+        }
+
+        self.world.touch(selected_id);
+        self.world.evict(&preload_ids);
+
+        self.camera.orientation = self.world.get_orientation(selected_id);
+        self.fit_camera();
+        self.playback.select(selected_id, now);
+        let world = &self.world;
+        self.playback.update(
+            |index| world.frame(selected_id, index).map(|frame| frame.delay),
+            world.frame_count(selected_id),
+            now,
+        );
+        self.world.trim_frames(selected_id, self.playback.frame);
+        self.request_frames(selected_id, &filters);
+        let error = match self.world.load_state(selected_id) {
+            LoadState::Failed(error) => {
+                let name = self
+                    .world
+                    .get_path(selected_id)
+                    .and_then(|path| path.file_name())
+                    .map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+                Some(format!("Can't show {name}\n{error}"))
+            }
+            LoadState::Pending | LoadState::Loaded => None,
+        };
+        if error.is_some() {
+            // Not the previous image, that would look like the broken one.
+            self.transition.clear();
+        }
+        self.hud.set_error(error);
+        let current = self
+            .current_frame()
+            .map(|image| (image.clone(), self.camera.corners(image.size().as_vec2())));
+        self.transition
+            .update(selected_id, self.selected, self.world.len(), current, now);
+        if self.hud.is_shown() {
+            self.world.read_file_size(selected_id);
+            let text = self.info_text(selected_id);
+            self.hud.set_text(text);
+        }
+        let title = self.window_title(selected_id);
+        if title != self.title {
+            self.window_requests
+                .push(WindowRequest::SetTitle(title.clone()));
+            self.title = title;
+        }
+    }
+
+    /// Asks for the thumbnails on screen in the gallery and a couple of rows around them,
+    /// returns the images those are for.
+    fn request_thumbnails(&mut self) -> Vec<EntityId> {
+        let size = self.gallery.thumbnail_size();
+        let wanted: Vec<EntityId> = self
+            .gallery
+            .visible(self.viewport_size, self.world.len(), 2)
+            .filter_map(|index| self.world.id_at(index))
+            .collect();
+        for &id in &wanted {
+            let failed = matches!(self.world.load_state(id), LoadState::Failed(_));
+            if failed || self.world.get_thumbnail(id).is_some() {
+                continue;
+            }
+            if let Some(path) = self.world.get_path(id) {
+                self.task_manager.thumbnail(id, path.clone(), size);
+            }
+        }
+        wanted
+    }
+
+    /// Moves the slideshow on when its time is up and the next image is ready.
+    /// It waits while the gallery is open.
+    fn advance_slideshow(&mut self, now: Instant) {
+        if self.gallery.open {
+            return;
+        }
+        let Some(upcoming) = self.slideshow.upcoming(self.selected, self.world.order()) else {
+            if self.world.len() > 1 && !self.task_manager.is_scanning() {
+                self.slideshow.finish();
             }
+            return;
+        };
+        // Broken images get their turn too, to show why they can't be shown.
+        let settled = |id| self.world.load_state(id) != LoadState::Pending;
+        let shown = self.current_frame().is_some() || self.selected_id().is_some_and(settled);
+        let ready = self.world.id_at(upcoming).is_some_and(settled);
+        if !self.slideshow.advance(shown, ready, now) {
+            return;
         }
+        self.start_at = None;
+        self.forward = true;
+        self.selected = upcoming;
+    }
 
-        self.task_manager.update(&mut self.world, graphics);
+    /// Moves past the selected image if it is broken and `skip_broken` is set, the same way
+    /// as the last move. Returns whether it did.
+    fn skip_broken(&mut self, id: EntityId) -> bool {
+        let LoadState::Failed(error) = self.world.load_state(id) else {
+            if self.world.load_state(id) == LoadState::Loaded {
+                self.skipped_from = None;
+            }
+            return false;
+        };
+        if !self.config.skip_broken || self.gallery.open {
+            return false;
+        }
+        let skipped_from = *self.skipped_from.get_or_insert(id);
+        let len = self.world.len();
+        let next = if self.forward {
+            (self.selected + 1) % len
+        } else {
+            (self.selected + len - 1) % len
+        };
+        if self.world.id_at(next) == Some(skipped_from) {
+            return false;
+        }
+        if let Some(path) = self.world.get_path(id) {
+            println!("Skipping {}: {error}", path.display());
+        }
+        self.selected = next;
+        true
+    }
+
+    /// The frame of the selected image that is on screen, if it is loaded.
+    fn current_frame(&self) -> Option<&ImageHandle> {
+        let id = self.selected_id()?;
+        self.world
+            .frame(id, self.playback.frame)
+            .map(|frame| &frame.image)
+            .or_else(|| self.world.get_image(id))
+    }
+
+    /// Where playback is in an animation, `None` for still images.
+    fn frame_label(&self, id: EntityId) -> Option<String> {
+        let frame = self.playback.frame + 1;
+        match self.world.frame_count(id) {
+            Some(0 | 1) => None,
+            Some(count) => Some(format!("frame {frame} / {count}")),
+            None => self.world.get_image(id).map(|_| format!("frame {frame}")),
+        }
+    }
+
+    /// Takes the window changes asked for since last time.
+    pub fn window_requests(&mut self) -> Vec<WindowRequest> {
+        std::mem::take(&mut self.window_requests)
+    }
+
+    /// Fills in `title_format` from the config for the selected image.
+    #[allow(clippy::literal_string_with_formatting_args)]
+    fn window_title(&self, id: EntityId) -> String {
+        let path = self.world.get_path(id);
+        let name = path
+            .and_then(|path| path.file_name())
+            .map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+        let dir = path
+            .and_then(|path| path.parent())
+            .map_or_else(String::new, |dir| {
+                dir.file_name().map_or_else(
+                    || dir.display().to_string(),
+                    |name| name.to_string_lossy().into_owned(),
+                )
+            });
+        let size = self.world.get_image(id).map(|image| *image.size());
+        let (width, height) = size.map_or_else(Default::default, |size| {
+            (size.x.to_string(), size.y.to_string())
+        });
+        self.config
+            .title_format
+            .replace("{name}", &name)
+            .replace("{index}", &(self.selected + 1).to_string())
+            .replace("{count}", &self.world.len().to_string())
+            .replace("{width}", &width)
+            .replace("{height}", &height)
+            .replace("{zoom}", &format!("{:.0}%", self.camera.scale * 100.0))
+            .replace("{dir}", &dir)
+            .replace("{title}", &self.config.title)
+    }
+
+    /// What the overlay shows about the selected image.
+    fn info_text(&self, id: EntityId) -> String {
+        let path = self.world.get_path(id);
+        let name = path
+            .and_then(|path| path.file_name())
+            .map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+
+        let mut details = vec![format!("{} / {}", self.selected + 1, self.world.len())];
+        if let Some(image) = self.world.get_image(id) {
+            details.push(format!("{} × {}", image.size().x, image.size().y));
+        }
+        if let Some(size) = self.world.file_size(id) {
+            details.push(hud::format_size(size));
+        }
+        if let Some(format) = self.world.format(id) {
+            details.push(format!("{format:?}"));
+        }
+
+        let mut view = vec![format!("{:.0}%", self.camera.scale * 100.0)];
+        view.extend(self.frame_label(id));
+        if self.filter != FilterKind::None {
+            view.push(self.filter.to_string());
+        }
+        if self.inverted {
+            view.push("inverted".to_string());
+        }
+
+        [name, details.join("   "), view.join("   ")].join("\n")
     }
 
     pub fn draw(&self, graphics: &mut Graphics2D) {
@@ -168,20 +950,13 @@ impl Game {
         //     robot.draw(&Rect::new(center, center + Vec2::new(50.0, 50.0)), graphics);
         // }
 
-        if let Some(image_handle) = &self.world.get_image(self.selected) {
-            let size = image_handle.size();
-            let bounds = Rect::new(Vec2::ZERO, Vec2::new(size.x as f32, size.y as f32));
-
-            if self.inverted {
-                // TODO: atm its making the image less bright instead. 
-                graphics.draw_rectangle_image_tinted(
-                    self.camera.transform(&bounds),
-                    Color::from_gray(0.5),
-                    image_handle,
-                );
-            } else {
-                graphics.draw_rectangle_image(self.camera.transform(&bounds), image_handle);
-            }
-        };
+        if self.gallery.open {
+            self.gallery
+                .draw(graphics, self.viewport_size, &self.world, self.selected);
+        } else {
+            self.transition.draw(graphics, self.viewport_size);
+            self.hud.draw_error(graphics, self.viewport_size);
+        }
+        self.hud.draw(graphics, self.viewport_size);
     }
 }
@@ -0,0 +1,97 @@
+use std::time::{Duration, Instant};
+
+use crate::game::world::{EntityId, FRAME_WINDOW};
+
+const MIN_SPEED: f32 = 0.125;
+const MAX_SPEED: f32 = 8.0;
+
+/// Playback position in the selected image's frames.
+pub struct Playback {
+    id: Option<EntityId>,
+    pub frame: usize,
+    pub paused: bool,
+    pub speed: f32,
+    frame_started: Instant,
+}
+
+impl Playback {
+    pub fn new() -> Self {
+        Self {
+            id: None,
+            frame: 0,
+            paused: false,
+            speed: 1.0,
+            frame_started: Instant::now(),
+        }
+    }
+
+    /// Restarts from the first frame when a different image gets selected.
+    pub fn select(&mut self, id: EntityId, now: Instant) {
+        if self.id != Some(id) {
+            self.id = Some(id);
+            self.frame = 0;
+            self.frame_started = now;
+        }
+    }
+
+    /// Advances past every frame whose delay has elapsed, given the delays of the frames
+    /// decoded so far. Playback waits on frames that are still decoding instead of
+    /// skipping them, and only loops once the frame count is known.
+    pub fn update(
+        &mut self,
+        delay_of: impl Fn(usize) -> Option<Duration>,
+        frame_count: Option<usize>,
+        now: Instant,
+    ) {
+        if frame_count.is_some_and(|count| self.frame >= count) {
+            self.frame = 0;
+        }
+        if self.paused || frame_count.is_some_and(|count| count < 2) {
+            self.frame_started = now;
+            return;
+        }
+
+        // Bounded, so a long stall skips ahead instead of fast-forwarding through frames.
+        for _ in 0..FRAME_WINDOW {
+            let Some(delay) = delay_of(self.frame) else {
+                self.frame_started = now;
+                return;
+            };
+            let delay = delay.div_f32(self.speed);
+            if now.duration_since(self.frame_started) < delay {
+                return;
+            }
+            let next = match frame_count {
+                Some(count) if self.frame + 1 >= count => 0,
+                _ => self.frame + 1,
+            };
+            if delay_of(next).is_none() {
+                return;
+            }
+            self.frame = next;
+            self.frame_started += delay;
+        }
+        self.frame_started = now;
+    }
+
+    pub const fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    /// Pauses and moves one frame forward or back, wrapping around once the frame count
+    /// is known.
+    pub const fn step(&mut self, frame_count: Option<usize>, forward: bool) {
+        self.paused = true;
+        self.frame = match (forward, frame_count) {
+            (true, Some(count)) if self.frame + 1 >= count => 0,
+            (true, _) => self.frame + 1,
+            (false, Some(count)) if self.frame == 0 => count.saturating_sub(1),
+            (false, _) => self.frame.saturating_sub(1),
+        };
+    }
+
+    pub fn change_speed(&mut self, faster: bool) {
+        let factor = if faster { 2.0 } else { 0.5 };
+        self.speed = (self.speed * factor).clamp(MIN_SPEED, MAX_SPEED);
+    }
+}
@@ -1,40 +1,137 @@
-use glam::Vec2;
-use speedy2d::window::MouseButton;
-use speedy2d::Rect;
+use glam::{Affine2, Vec2};
+use strum_macros::{Display, EnumIter, EnumString};
 
-use crate::app::{Keyboard, Mouse};
+/// How an image is placed in the viewport when it is opened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, EnumString, EnumIter)]
+#[strum(serialize_all = "snake_case")]
+pub enum ViewMode {
+    /// The whole image is visible.
+    Fit,
+    /// The image covers the whole viewport.
+    Fill,
+    /// One image pixel per screen pixel.
+    ActualSize,
+    FitWidth,
+    FitHeight,
+}
+
+/// How an image is turned and mirrored, kept for each image separately.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Orientation {
+    /// Clockwise, in degrees.
+    pub rotation: f32,
+    pub flip_x: bool,
+    pub flip_y: bool,
+}
+
+impl Orientation {
+    pub fn rotate(&mut self, degrees: f32) {
+        self.rotation = (self.rotation + degrees).rem_euclid(360.0);
+    }
+
+    /// Mirrors along the screen's vertical axis, whichever way the image is turned.
+    pub fn flip_horizontal(&mut self) {
+        self.flip_x = !self.flip_x;
+        self.rotation = (-self.rotation).rem_euclid(360.0);
+    }
+
+    /// Mirrors along the screen's horizontal axis, whichever way the image is turned.
+    pub fn flip_vertical(&mut self) {
+        self.flip_y = !self.flip_y;
+        self.rotation = (-self.rotation).rem_euclid(360.0);
+    }
+
+    /// The size of the box the turned image fits in.
+    fn bounds(self, size: Vec2) -> Vec2 {
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        Vec2::new(
+            (size.x * cos).abs() + (size.y * sin).abs(),
+            (size.x * sin).abs() + (size.y * cos).abs(),
+        )
+    }
+}
 
 #[derive(Debug)]
 pub struct Camera {
+    /// Where the center of the image is on screen.
     pub offset: Vec2,
     pub scale: f32,
+    pub orientation: Orientation,
+    /// The center of the image, which it is turned around.
+    pivot: Vec2,
+    pub mode: ViewMode,
+    /// Set once the user pans or zooms, so resizing the window keeps their view.
+    pub manual: bool,
 }
 
 impl Camera {
-    pub const fn new() -> Self {
+    pub const fn new(mode: ViewMode) -> Self {
         Self {
             offset: Vec2::ZERO,
             scale: 1.0,
+            orientation: Orientation {
+                rotation: 0.0,
+                flip_x: false,
+                flip_y: false,
+            },
+            pivot: Vec2::ZERO,
+            mode,
+            manual: false,
+        }
+    }
+
+    /// Scales the image according to `mode` and centers it in the viewport.
+    pub fn fit(&mut self, image_size: Vec2, viewport_size: Vec2) {
+        if image_size.x <= 0.0 || image_size.y <= 0.0 {
+            return;
         }
+        let ratio = viewport_size / self.orientation.bounds(image_size);
+        self.scale = match self.mode {
+            ViewMode::Fit => ratio.min_element(),
+            ViewMode::Fill => ratio.max_element(),
+            ViewMode::ActualSize => 1.0,
+            ViewMode::FitWidth => ratio.x,
+            ViewMode::FitHeight => ratio.y,
+        };
+        self.pivot = image_size / 2.0;
+        self.offset = viewport_size / 2.0;
+        self.manual = false;
+    }
+
+    /// Image pixels to screen: mirrored, scaled and turned around the image center,
+    /// then moved to `offset`.
+    fn affine(&self) -> Affine2 {
+        let flip = Vec2::new(
+            if self.orientation.flip_x { -1.0 } else { 1.0 },
+            if self.orientation.flip_y { -1.0 } else { 1.0 },
+        );
+        Affine2::from_translation(self.offset)
+            * Affine2::from_angle(self.orientation.rotation.to_radians())
+            * Affine2::from_scale(flip * self.scale)
+            * Affine2::from_translation(-self.pivot)
     }
 
     pub fn world_to_screen(&self, world_pos: Vec2) -> Vec2 {
-        world_pos * self.scale + self.offset
+        self.affine().transform_point2(world_pos)
     }
 
     pub fn screen_to_world(&self, screen_pos: Vec2) -> Vec2 {
-        (screen_pos - self.offset) / self.scale
+        self.affine().inverse().transform_point2(screen_pos)
     }
 
     pub fn transform_point(&self, point: Vec2) -> Vec2 {
         self.world_to_screen(point)
     }
 
-    pub fn transform(&self, rect: &Rect) -> Rect {
-        Rect::new(
-            self.world_to_screen(rect.top_left),
-            self.world_to_screen(rect.bottom_right),
-        )
+    /// The corners of an image of `size` on screen, clockwise from its top left.
+    pub fn corners(&self, size: Vec2) -> [Vec2; 4] {
+        [
+            Vec2::ZERO,
+            Vec2::new(size.x, 0.0),
+            size,
+            Vec2::new(0.0, size.y),
+        ]
+        .map(|corner| self.world_to_screen(corner))
     }
 
     fn calculate_scale(scroll_lines: f64) -> f32 {
@@ -42,26 +139,35 @@ impl Camera {
         zoom_speed.powf(scroll_lines as f32)
     }
 
+    /// Pans along with the mouse while `pan` is held and zooms by `zoom_lines` toward it.
     pub fn handle_input(
         &mut self,
-        mouse: &Mouse,
+        mouse_position: Vec2,
         mouse_delta: Vec2,
-        scroll_delta: f64,
-        _keyboard: &Keyboard,
+        pan: bool,
+        zoom_lines: f64,
     ) {
-        if mouse.pressed.contains(&MouseButton::Left) {
-            self.offset += mouse_delta;
+        if pan && mouse_delta != Vec2::ZERO {
+            self.pan(mouse_delta);
+        }
+        if zoom_lines != 0.0 {
+            self.zoom(mouse_position, zoom_lines);
         }
+    }
 
-        let scale_change = Self::calculate_scale(scroll_delta);
-        if scroll_delta != 0.0 {
-            let mouse_world_before = self.screen_to_world(mouse.position);
-            self.scale *= scale_change;
-            let mouse_world_after = self.screen_to_world(mouse.position);
+    /// Moves the view by `delta` screen pixels.
+    pub fn pan(&mut self, delta: Vec2) {
+        self.offset += delta;
+        self.manual = true;
+    }
 
-            // Move the camera so the world point under the mouse stays under the cursor
-            let correction = (mouse_world_after - mouse_world_before) * self.scale;
-            self.offset += correction;
-        }
+    /// Zooms in by `lines`, out if negative, keeping the point under `anchor` in place.
+    pub fn zoom(&mut self, anchor: Vec2, lines: f64) {
+        let anchor_world = self.screen_to_world(anchor);
+        self.scale *= Self::calculate_scale(-lines);
+
+        // Move the camera so the world point under the mouse stays under the cursor
+        self.offset += anchor - self.world_to_screen(anchor_world);
+        self.manual = true;
     }
 }
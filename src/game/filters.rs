@@ -0,0 +1,227 @@
+use strum_macros::{Display, EnumIter, EnumString};
+
+/// Color filters that run on raw RGBA8 pixel buffers, independent of the window.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Filter {
+    Invert,
+    Grayscale,
+    Sepia,
+    /// `brightness` is added in the -1.0..1.0 range, `contrast` scales around mid gray.
+    BrightnessContrast {
+        brightness: f32,
+        contrast: f32,
+    },
+    Gamma(f32),
+    Channel(Channel),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    Red,
+    Green,
+    Blue,
+    Alpha,
+}
+
+/// The filters that can be picked from config.ini or cycled at runtime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, EnumString, EnumIter)]
+#[strum(serialize_all = "snake_case")]
+pub enum FilterKind {
+    None,
+    Grayscale,
+    Sepia,
+    BrightnessContrast,
+    Gamma,
+    Red,
+    Green,
+    Blue,
+    Alpha,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FilterParams {
+    pub brightness: f32,
+    pub contrast: f32,
+    pub gamma: f32,
+}
+
+impl FilterKind {
+    pub const fn filter(self, params: FilterParams) -> Option<Filter> {
+        match self {
+            Self::None => None,
+            Self::Grayscale => Some(Filter::Grayscale),
+            Self::Sepia => Some(Filter::Sepia),
+            Self::BrightnessContrast => Some(Filter::BrightnessContrast {
+                brightness: params.brightness,
+                contrast: params.contrast,
+            }),
+            Self::Gamma => Some(Filter::Gamma(params.gamma)),
+            Self::Red => Some(Filter::Channel(Channel::Red)),
+            Self::Green => Some(Filter::Channel(Channel::Green)),
+            Self::Blue => Some(Filter::Channel(Channel::Blue)),
+            Self::Alpha => Some(Filter::Channel(Channel::Alpha)),
+        }
+    }
+}
+
+impl Filter {
+    pub fn apply(&self, pixels: &mut [u8]) {
+        match *self {
+            Self::Invert => map_rgb(pixels, &lut(|v| 1.0 - v)),
+            Self::Grayscale => {
+                for px in pixels.chunks_exact_mut(4) {
+                    let luma = luma(px[0], px[1], px[2]);
+                    px[0] = luma;
+                    px[1] = luma;
+                    px[2] = luma;
+                }
+            }
+            Self::Sepia => {
+                for px in pixels.chunks_exact_mut(4) {
+                    let (r, g, b) = (f32::from(px[0]), f32::from(px[1]), f32::from(px[2]));
+                    px[0] = to_u8(0.393f32.mul_add(r, 0.769f32.mul_add(g, 0.189 * b)));
+                    px[1] = to_u8(0.349f32.mul_add(r, 0.686f32.mul_add(g, 0.168 * b)));
+                    px[2] = to_u8(0.272f32.mul_add(r, 0.534f32.mul_add(g, 0.131 * b)));
+                }
+            }
+            Self::BrightnessContrast {
+                brightness,
+                contrast,
+            } => map_rgb(
+                pixels,
+                &lut(|v| (v - 0.5).mul_add(contrast, 0.5) + brightness),
+            ),
+            Self::Gamma(gamma) => {
+                let exponent = if gamma > 0.0 { gamma.recip() } else { 1.0 };
+                map_rgb(pixels, &lut(|v| v.powf(exponent)));
+            }
+            Self::Channel(channel) => {
+                for px in pixels.chunks_exact_mut(4) {
+                    match channel {
+                        Channel::Red => (px[1], px[2]) = (0, 0),
+                        Channel::Green => (px[0], px[2]) = (0, 0),
+                        Channel::Blue => (px[0], px[1]) = (0, 0),
+                        Channel::Alpha => {
+                            (px[0], px[1], px[2], px[3]) = (px[3], px[3], px[3], u8::MAX);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Runs every filter in order over an RGBA8 buffer.
+pub fn apply_all(filters: &[Filter], pixels: &mut [u8]) {
+    for filter in filters {
+        filter.apply(pixels);
+    }
+}
+
+/// Builds a lookup table from a function over normalized 0.0..=1.0 channel values.
+fn lut(f: impl Fn(f32) -> f32) -> [u8; 256] {
+    let mut table = [0; 256];
+    for (value, entry) in (0..=u8::MAX).zip(table.iter_mut()) {
+        *entry = to_u8(f(f32::from(value) / 255.0) * 255.0);
+    }
+    table
+}
+
+fn map_rgb(pixels: &mut [u8], table: &[u8; 256]) {
+    for px in pixels.chunks_exact_mut(4) {
+        px[0] = table[px[0] as usize];
+        px[1] = table[px[1] as usize];
+        px[2] = table[px[2] as usize];
+    }
+}
+
+fn luma(r: u8, g: u8, b: u8) -> u8 {
+    to_u8(0.299f32.mul_add(
+        f32::from(r),
+        0.587f32.mul_add(f32::from(g), 0.114 * f32::from(b)),
+    ))
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+const fn to_u8(value: f32) -> u8 {
+    value.round().clamp(0.0, 255.0) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEFAULTS: FilterParams = FilterParams {
+        brightness: 0.0,
+        contrast: 1.0,
+        gamma: 1.0,
+    };
+
+    /// Every channel value in every position, with the alpha channel varying too.
+    fn pixels() -> Vec<u8> {
+        (0..=u8::MAX)
+            .flat_map(|v| [v, v.wrapping_mul(7), v.wrapping_add(91), v.wrapping_mul(3)])
+            .collect()
+    }
+
+    fn filtered(filter: Filter) -> Vec<u8> {
+        let mut pixels = pixels();
+        filter.apply(&mut pixels);
+        pixels
+    }
+
+    #[test]
+    fn invert_twice_is_the_original() {
+        let mut pixels = pixels();
+        apply_all(&[Filter::Invert, Filter::Invert], &mut pixels);
+        assert_eq!(pixels, self::pixels());
+    }
+
+    #[test]
+    fn invert_keeps_alpha() {
+        for (before, after) in pixels()
+            .chunks_exact(4)
+            .zip(filtered(Filter::Invert).chunks_exact(4))
+        {
+            assert_eq!(
+                after,
+                [255 - before[0], 255 - before[1], 255 - before[2], before[3]]
+            );
+        }
+    }
+
+    #[test]
+    fn grayscale_has_equal_channels() {
+        for px in filtered(Filter::Grayscale).chunks_exact(4) {
+            assert!(px[0] == px[1] && px[1] == px[2], "{px:?}");
+        }
+    }
+
+    #[test]
+    fn channel_isolation_zeroes_the_others() {
+        let cases = [(Channel::Red, 0), (Channel::Green, 1), (Channel::Blue, 2)];
+        for (channel, kept) in cases {
+            let after = filtered(Filter::Channel(channel));
+            for (before, after) in pixels().chunks_exact(4).zip(after.chunks_exact(4)) {
+                for index in 0..3 {
+                    let expected = if index == kept { before[index] } else { 0 };
+                    assert_eq!(after[index], expected, "{channel:?}");
+                }
+                assert_eq!(after[3], before[3]);
+            }
+        }
+        let alpha = filtered(Filter::Channel(Channel::Alpha));
+        for (before, after) in pixels().chunks_exact(4).zip(alpha.chunks_exact(4)) {
+            assert_eq!(after, [before[3], before[3], before[3], 255]);
+        }
+    }
+
+    #[test]
+    fn defaults_change_nothing() {
+        for kind in [FilterKind::BrightnessContrast, FilterKind::Gamma] {
+            let filter = kind.filter(DEFAULTS).unwrap();
+            assert_eq!(filtered(filter), pixels(), "{kind}");
+        }
+        assert_eq!(FilterKind::None.filter(DEFAULTS), None);
+    }
+}
@@ -0,0 +1,146 @@
+use std::ops::Range;
+
+use glam::{UVec2, Vec2};
+use speedy2d::color::Color;
+use speedy2d::{Graphics2D, Rect};
+
+use crate::config::Config;
+use crate::game::world::{LoadState, World};
+
+/// Space between thumbnails and around the grid.
+const PADDING: f32 = 12.0;
+/// How far one wheel line scrolls the grid, in pixels.
+const SCROLL_PER_LINE: f32 = 40.0;
+
+/// A scrollable grid of thumbnails, one cell per image in display order.
+pub struct Gallery {
+    pub open: bool,
+    thumbnail_size: u32,
+    /// How far down the grid is scrolled, in pixels.
+    scroll: f32,
+}
+
+impl Gallery {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            open: false,
+            thumbnail_size: config.thumbnail_size.max(16),
+            scroll: 0.0,
+        }
+    }
+
+    pub const fn thumbnail_size(&self) -> u32 {
+        self.thumbnail_size
+    }
+
+    fn cell(&self) -> f32 {
+        self.thumbnail_size as f32 + PADDING
+    }
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn columns(&self, viewport_size: UVec2) -> usize {
+        let columns = (viewport_size.as_vec2().x - PADDING) / self.cell();
+        (columns.floor() as usize).max(1)
+    }
+
+    /// Where the cell of `index` starts on screen.
+    #[allow(clippy::cast_precision_loss)]
+    fn cell_position(&self, index: usize, viewport_size: UVec2) -> Vec2 {
+        let columns = self.columns(viewport_size);
+        let grid_width = (columns as f32).mul_add(self.cell(), -PADDING);
+        let left = ((viewport_size.as_vec2().x - grid_width) / 2.0).max(PADDING);
+        let (row, column) = (index / columns, index % columns);
+        let cell = Vec2::new(column as f32, row as f32) * self.cell();
+        Vec2::new(left, PADDING - self.scroll) + cell
+    }
+
+    /// How far the grid can be scrolled before its last row leaves the bottom.
+    #[allow(clippy::cast_precision_loss)]
+    fn max_scroll(&self, viewport_size: UVec2, count: usize) -> f32 {
+        let rows = count.div_ceil(self.columns(viewport_size));
+        let height = (rows as f32).mul_add(self.cell(), PADDING);
+        (height - viewport_size.as_vec2().y).max(0.0)
+    }
+
+    /// The images with cells on screen, and `extra_rows` more above and below.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn visible(&self, viewport_size: UVec2, count: usize, extra_rows: usize) -> Range<usize> {
+        let columns = self.columns(viewport_size);
+        let first_row = ((self.scroll - PADDING) / self.cell()).floor().max(0.0) as usize;
+        let last_row = ((self.scroll + viewport_size.as_vec2().y) / self.cell()).ceil() as usize;
+        let start = first_row.saturating_sub(extra_rows) * columns;
+        let end = (last_row + extra_rows + 1) * columns;
+        start.min(count)..end.min(count)
+    }
+
+    /// The image whose cell is under `position`.
+    pub fn index_at(&self, position: Vec2, viewport_size: UVec2, count: usize) -> Option<usize> {
+        self.visible(viewport_size, count, 0).find(|index| {
+            let top_left = self.cell_position(*index, viewport_size);
+            let size = Vec2::splat(self.cell() - PADDING);
+            Rect::new(top_left, top_left + size).contains(position)
+        })
+    }
+
+    /// Scrolls by wheel lines, positive being up.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn scroll_lines(&mut self, lines: f64, viewport_size: UVec2, count: usize) {
+        self.scroll_by(-(lines as f32) * SCROLL_PER_LINE, viewport_size, count);
+    }
+
+    pub fn scroll_by(&mut self, pixels: f32, viewport_size: UVec2, count: usize) {
+        self.scroll = (self.scroll + pixels).clamp(0.0, self.max_scroll(viewport_size, count));
+    }
+
+    /// Scrolls just far enough for the cell of `index` to be on screen.
+    pub fn reveal(&mut self, index: usize, viewport_size: UVec2, count: usize) {
+        let top = self.cell_position(index, viewport_size).y;
+        let bottom = top + self.cell();
+        if top < 0.0 {
+            self.scroll_by(top - PADDING, viewport_size, count);
+        } else if bottom > viewport_size.as_vec2().y {
+            self.scroll_by(bottom - viewport_size.as_vec2().y, viewport_size, count);
+        }
+    }
+
+    pub fn draw(
+        &self,
+        graphics: &mut Graphics2D,
+        viewport_size: UVec2,
+        world: &World,
+        selected: usize,
+    ) {
+        let size = self.cell() - PADDING;
+        for index in self.visible(viewport_size, world.len(), 0) {
+            let top_left = self.cell_position(index, viewport_size);
+            if index == selected {
+                let border = Vec2::splat(PADDING / 2.0);
+                graphics.draw_rectangle(
+                    Rect::new(top_left - border, top_left + Vec2::splat(size) + border),
+                    Color::from_rgba(1.0, 1.0, 1.0, 0.35),
+                );
+            }
+            let id = world.id_at(index);
+            let Some(thumbnail) = id.and_then(|id| world.get_thumbnail(id)) else {
+                let broken =
+                    id.is_some_and(|id| matches!(world.load_state(id), LoadState::Failed(_)));
+                let color = if broken {
+                    Color::from_rgba(0.8, 0.1, 0.1, 0.3)
+                } else {
+                    Color::from_rgba(1.0, 1.0, 1.0, 0.08)
+                };
+                graphics.draw_rectangle(Rect::new(top_left, top_left + Vec2::splat(size)), color);
+                continue;
+            };
+            // Centered in the cell, small images are not blown up.
+            let thumbnail_size = thumbnail.size().as_vec2();
+            let scale = (size / thumbnail_size.max_element()).min(1.0);
+            let drawn = thumbnail_size * scale;
+            let offset = (Vec2::splat(size) - drawn) / 2.0;
+            graphics.draw_rectangle_image(
+                Rect::new(top_left + offset, top_left + offset + drawn),
+                thumbnail,
+            );
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use std::rc::Rc;
+
+use glam::{UVec2, Vec2};
+use speedy2d::color::Color;
+use speedy2d::font::{Font, FormattedTextBlock, TextAlignment, TextLayout, TextOptions};
+use speedy2d::{Graphics2D, Rect};
+use strum_macros::{Display, EnumString};
+
+use crate::config::Config;
+
+const FONT: &[u8] = include_bytes!("../../assets/DejaVuSansMono.ttf");
+const FONT_SIZE: f32 = 16.0;
+const MARGIN: f32 = 8.0;
+const PADDING: f32 = 6.0;
+/// Error messages wrap at this width.
+const ERROR_WIDTH: f32 = 480.0;
+
+/// Which corner of the window the overlay sits in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum HudPosition {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// A text overlay with information about the current image.
+pub struct Hud {
+    font: Option<Font>,
+    visible: bool,
+    /// Shown or hidden separately in fullscreen.
+    fullscreen_visible: bool,
+    fullscreen: bool,
+    position: HudPosition,
+    opacity: f32,
+    text: String,
+    layout: Option<Rc<FormattedTextBlock>>,
+    /// Why the selected image can't be shown, with its layout.
+    error: Option<(String, Rc<FormattedTextBlock>)>,
+}
+
+impl Hud {
+    pub fn new(config: &Config) -> Self {
+        let font = match Font::new(FONT) {
+            Ok(font) => Some(font),
+            Err(err) => {
+                eprintln!("Failed to load the overlay font: {err:?}");
+                None
+            }
+        };
+        Self {
+            font,
+            visible: config.hud,
+            fullscreen_visible: config.fullscreen_hud,
+            fullscreen: config.fullscreen,
+            position: config.hud_position,
+            opacity: config.hud_opacity.clamp(0.0, 1.0),
+            text: String::new(),
+            layout: None,
+            error: None,
+        }
+    }
+
+    /// Lays the text out again, only if it changed.
+    pub fn set_text(&mut self, text: String) {
+        if text == self.text {
+            return;
+        }
+        self.layout = self
+            .font
+            .as_ref()
+            .map(|font| font.layout_text(&text, FONT_SIZE, TextOptions::new()));
+        self.text = text;
+    }
+
+    /// Sets the message shown in place of an image that couldn't be loaded.
+    pub fn set_error(&mut self, error: Option<String>) {
+        if error.as_deref() == self.error.as_ref().map(|(text, _)| text.as_str()) {
+            return;
+        }
+        self.error = error.zip(self.font.as_ref()).map(|(text, font)| {
+            let options = TextOptions::new().with_wrap_to_width(ERROR_WIDTH, TextAlignment::Center);
+            let layout = font.layout_text(&text, FONT_SIZE, options);
+            (text, layout)
+        });
+    }
+
+    pub const fn toggle(&mut self) {
+        if self.fullscreen {
+            self.fullscreen_visible = !self.fullscreen_visible;
+        } else {
+            self.visible = !self.visible;
+        }
+    }
+
+    pub const fn set_fullscreen(&mut self, fullscreen: bool) {
+        self.fullscreen = fullscreen;
+    }
+
+    pub const fn is_shown(&self) -> bool {
+        let visible = if self.fullscreen {
+            self.fullscreen_visible
+        } else {
+            self.visible
+        };
+        visible && self.font.is_some()
+    }
+
+    pub fn draw(&self, graphics: &mut Graphics2D, viewport_size: UVec2) {
+        if !self.is_shown() || self.text.is_empty() {
+            return;
+        }
+        let Some(layout) = &self.layout else {
+            return;
+        };
+        let size = Vec2::new(layout.width(), layout.height()) + Vec2::splat(PADDING * 2.0);
+        let free = viewport_size.as_vec2() - size - Vec2::splat(MARGIN);
+        let top_left = match self.position {
+            HudPosition::TopLeft => Vec2::splat(MARGIN),
+            HudPosition::TopRight => Vec2::new(free.x, MARGIN),
+            HudPosition::BottomLeft => Vec2::new(MARGIN, free.y),
+            HudPosition::BottomRight => free,
+        };
+        graphics.draw_rectangle(
+            Rect::new(top_left, top_left + size),
+            Color::from_rgba(0.0, 0.0, 0.0, self.opacity * 0.6),
+        );
+        graphics.draw_text(
+            top_left + Vec2::splat(PADDING),
+            Color::from_rgba(1.0, 1.0, 1.0, self.opacity),
+            layout,
+        );
+    }
+
+    /// Draws the error message in the middle of the window, whether the overlay is shown or not.
+    pub fn draw_error(&self, graphics: &mut Graphics2D, viewport_size: UVec2) {
+        let Some((_, layout)) = &self.error else {
+            return;
+        };
+        let size = Vec2::new(layout.width(), layout.height()) + Vec2::splat(PADDING * 2.0);
+        let top_left = (viewport_size.as_vec2() - size) / 2.0;
+        graphics.draw_rectangle(
+            Rect::new(top_left, top_left + size),
+            Color::from_rgba(0.4, 0.0, 0.0, 0.8),
+        );
+        graphics.draw_text(top_left + Vec2::splat(PADDING), Color::WHITE, layout);
+    }
+}
+
+/// A byte count in the largest unit that keeps it above one.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
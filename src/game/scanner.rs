@@ -0,0 +1,341 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use image::{ImageFormat, ImageReader};
+use walkdir::{DirEntry, WalkDir};
+
+use crate::config::Config;
+use crate::game::sorting::{self, SortKey};
+
+/// Collects the images to show from every input path, handing each one to `found`
+/// as soon as it is discovered. Directories are walked, files are taken as they are.
+/// A single file on its own opens its whole directory instead, see [`opened_file`].
+pub fn scan(config: &Config, found: &mut impl FnMut(PathBuf)) {
+    let opened = opened_file(config);
+    let file_types = FileTypes::new(config);
+    println!("Reading {} asset paths:", config.inputs.len());
+    for input in &config.inputs {
+        let path = Path::new(input);
+        if let Some(opened) = opened {
+            let dir = opened
+                .parent()
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .unwrap_or_else(|| Path::new("."));
+            scan_dir(config, &file_types, dir, Some(opened), found);
+        } else if path.is_file() {
+            if file_types.accepts(path) {
+                found(path.to_path_buf());
+            } else {
+                eprintln!("Unsupported file: {input}");
+            }
+        } else {
+            scan_dir(config, &file_types, path, None, found);
+        }
+    }
+}
+
+/// The file to open when pfiew is started with just one file, like file managers do.
+pub fn opened_file(config: &Config) -> Option<&Path> {
+    match config.inputs.as_slice() {
+        [input] if Path::new(input).is_file() => Some(Path::new(input)),
+        _ => None,
+    }
+}
+
+/// Walks `dir` for supported images. `opened` is always included, even when
+/// its extension or name would be filtered out.
+///
+/// Files are listed before subdirectories, so the images of each directory
+/// stay next to each other. Both are in name order, other sort keys are applied
+/// once the scan is done.
+fn scan_dir(
+    config: &Config,
+    file_types: &FileTypes,
+    dir: &Path,
+    opened: Option<&Path>,
+    found: &mut impl FnMut(PathBuf),
+) {
+    let max_depth = if config.recursive {
+        config.max_depth.unwrap_or(usize::MAX)
+    } else {
+        1
+    };
+    let sort_by_name = config.sort != SortKey::Unsorted;
+    let excludes: Vec<Exclude> = config.exclude.iter().map(|p| Exclude::new(p)).collect();
+
+    let mut walker = WalkDir::new(dir)
+        .follow_links(true)
+        .max_depth(max_depth)
+        .sort_by(move |a, b| {
+            let by_kind = is_dir(a).cmp(&is_dir(b));
+            if sort_by_name {
+                by_kind.then_with(|| sorting::compare_names(a.path(), b.path()))
+            } else {
+                by_kind
+            }
+        })
+        .into_iter()
+        .filter_entry(|entry| {
+            if entry.depth() == 0 {
+                return true;
+            }
+            let hidden = entry.file_name().to_string_lossy().starts_with('.');
+            let relative = entry
+                .path()
+                .strip_prefix(dir)
+                .unwrap_or_else(|_| entry.path());
+            let is_dir = entry.file_type().is_dir();
+            (config.show_hidden || !hidden)
+                && !excludes
+                    .iter()
+                    .any(|exclude| exclude.matches(relative, is_dir))
+        });
+
+    // Symlinks can lead to the same directory twice without forming a loop.
+    let mut visited = HashSet::new();
+    // The directory images are currently coming from and how many it had so far.
+    let mut current: Option<(PathBuf, usize)> = None;
+    let mut total = 0;
+    while let Some(entry) = walker.next() {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                if let Some(ancestor) = err.loop_ancestor() {
+                    let path = err.path().unwrap_or(dir).display();
+                    eprintln!("Skipping symlink loop {path} -> {}", ancestor.display());
+                } else {
+                    eprintln!("{err}");
+                }
+                continue;
+            }
+        };
+        if entry.file_type().is_dir() {
+            if let Ok(canonical) = entry.path().canonicalize() {
+                if !visited.insert(canonical) {
+                    walker.skip_current_dir();
+                }
+            }
+            continue;
+        }
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let is_opened = opened.is_some_and(|opened| {
+            entry.depth() == 1 && Some(entry.file_name()) == opened.file_name()
+        });
+        if is_opened
+            || (matches_glob(config.glob.as_deref(), entry.path())
+                && file_types.accepts(entry.path()))
+        {
+            let parent = entry.path().parent().unwrap_or(dir);
+            match &mut current {
+                Some((last, count)) if last == parent => *count += 1,
+                _ => {
+                    if let Some((last, count)) = current.take() {
+                        println!("In {}: {count} assets.", last.display());
+                    }
+                    current = Some((parent.to_path_buf(), 1));
+                }
+            }
+            total += 1;
+            found(entry.into_path());
+        }
+    }
+
+    if let Some((last, count)) = current {
+        println!("In {}: {count} assets.", last.display());
+    }
+    if total == 0 {
+        println!("In {}: 0 assets.", dir.display());
+    }
+}
+
+/// Entries aren't resolved yet while sorting, so symlinks need a look at their target.
+fn is_dir(entry: &DirEntry) -> bool {
+    entry.file_type().is_dir() || (entry.path_is_symlink() && entry.path().is_dir())
+}
+
+/// A gitignore-style exclude pattern. Patterns with a `/` are matched against the path
+/// relative to the scanned directory, others against any file or directory name.
+/// A trailing `/` only matches directories.
+struct Exclude {
+    pattern: String,
+    anchored: bool,
+    dir_only: bool,
+}
+
+impl Exclude {
+    fn new(pattern: &str) -> Self {
+        let dir_only = pattern.ends_with('/');
+        let pattern = pattern.trim_end_matches('/');
+        let anchored = pattern.contains('/');
+        Self {
+            pattern: pattern.trim_start_matches('/').to_string(),
+            anchored,
+            dir_only,
+        }
+    }
+
+    fn matches(&self, relative: &Path, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        if self.anchored {
+            glob_match(&self.pattern, &relative.to_string_lossy())
+        } else {
+            relative
+                .file_name()
+                .is_some_and(|name| glob_match(&self.pattern, &name.to_string_lossy()))
+        }
+    }
+}
+
+/// Whether a file that showed up after the scan would have been picked up by it.
+pub fn accepts(config: &Config, file_types: &FileTypes, path: &Path) -> bool {
+    let hidden = path
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+    (config.show_hidden || !hidden)
+        && matches_glob(config.glob.as_deref(), path)
+        && file_types.accepts(path)
+}
+
+/// Whether `path` is the file to start at, either the same path or one ending in it.
+pub fn is_start(path: &Path, start_at: &Path) -> bool {
+    path == start_at || path.ends_with(start_at)
+}
+
+/// Which files are images. Known extensions are taken on their name alone, anything
+/// else is opened to look at its first bytes, so misnamed files are found too.
+pub struct FileTypes {
+    extensions: HashSet<String>,
+    /// Never shown, whatever is inside.
+    removed: HashSet<String>,
+}
+
+impl FileTypes {
+    pub fn new(config: &Config) -> Self {
+        let removed: HashSet<String> = config
+            .remove_extensions
+            .iter()
+            .map(|ext| normalize_extension(ext))
+            .collect();
+        let extensions = ImageFormat::all()
+            .filter(ImageFormat::reading_enabled)
+            .flat_map(ImageFormat::extensions_str)
+            .map(|ext| (*ext).to_string())
+            .chain(
+                config
+                    .add_extensions
+                    .iter()
+                    .map(|ext| normalize_extension(ext)),
+            )
+            .filter(|ext| !removed.contains(ext))
+            .collect();
+        Self {
+            extensions,
+            removed,
+        }
+    }
+
+    fn accepts(&self, path: &Path) -> bool {
+        let extension = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase());
+        match extension {
+            Some(ext) if self.extensions.contains(&ext) => true,
+            Some(ext) if self.removed.contains(&ext) => false,
+            _ => ImageReader::open(path)
+                .and_then(ImageReader::with_guessed_format)
+                .is_ok_and(|reader| reader.format().is_some_and(|f| f.reading_enabled())),
+        }
+    }
+}
+
+fn normalize_extension(ext: &str) -> String {
+    ext.trim_start_matches('.').to_lowercase()
+}
+
+fn matches_glob(glob: Option<&str>, path: &Path) -> bool {
+    let Some(glob) = glob else {
+        return true;
+    };
+    path.file_name()
+        .is_some_and(|name| glob_match(glob, &name.to_string_lossy()))
+}
+
+/// Matches like gitignore does: `*` is any run of characters and `?` any single one,
+/// neither crossing a `/`, while `**` matches across directories.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    glob_match_chars(&pattern, &text)
+}
+
+fn glob_match_chars(pattern: &[char], text: &[char]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        // Any number of whole directories, none too.
+        ['*', '*', '/', rest @ ..] => {
+            glob_match_chars(rest, text)
+                || (0..text.len())
+                    .any(|index| text[index] == '/' && glob_match_chars(rest, &text[index + 1..]))
+        }
+        ['*', '*', rest @ ..] => (0..=text.len()).any(|skip| glob_match_chars(rest, &text[skip..])),
+        ['*', rest @ ..] => (0..=text.len())
+            .take_while(|&skip| skip == 0 || text[skip - 1] != '/')
+            .any(|skip| glob_match_chars(rest, &text[skip..])),
+        ['?', rest @ ..] => {
+            matches!(text, [first, ..] if *first != '/') && glob_match_chars(rest, &text[1..])
+        }
+        [literal, rest @ ..] => text.first() == Some(literal) && glob_match_chars(rest, &text[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcards() {
+        assert!(glob_match("*.png", "a.png"));
+        assert!(glob_match("*.png", ".png"));
+        assert!(!glob_match("*.png", "a.jpg"));
+        assert!(glob_match("img?.png", "img1.png"));
+        assert!(!glob_match("img?.png", "img10.png"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("a*b*c", "abxxbyc"));
+        assert!(!glob_match("a*b*c", "abxxbyd"));
+    }
+
+    #[test]
+    fn single_wildcards_stay_in_one_directory() {
+        assert!(glob_match("raw/*", "raw/a.png"));
+        assert!(!glob_match("raw/*", "raw/2024/a.png"));
+        assert!(!glob_match("*.png", "raw/a.png"));
+        assert!(!glob_match("raw?a.png", "raw/a.png"));
+    }
+
+    #[test]
+    fn double_wildcards_cross_directories() {
+        assert!(glob_match("raw/**", "raw/2024/a.png"));
+        assert!(glob_match("**/thumbs", "thumbs"));
+        assert!(glob_match("**/thumbs", "a/b/thumbs"));
+        assert!(!glob_match("**/thumbs", "a/old_thumbs"));
+        assert!(glob_match("a/**/b", "a/b"));
+        assert!(glob_match("a/**/b", "a/x/y/b"));
+        assert!(!glob_match("a/**/b", "a/xb"));
+    }
+
+    #[test]
+    fn excludes() {
+        let name = Exclude::new("*.tmp");
+        assert!(name.matches(Path::new("deep/down/x.tmp"), false));
+        let anchored = Exclude::new("/raw/*.cr2");
+        assert!(anchored.matches(Path::new("raw/a.cr2"), false));
+        assert!(!anchored.matches(Path::new("other/raw/a.cr2"), false));
+        let dir = Exclude::new("cache/");
+        assert!(dir.matches(Path::new("a/cache"), true));
+        assert!(!dir.matches(Path::new("a/cache"), false));
+    }
+}
@@ -0,0 +1,112 @@
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+use crate::game::world::EntityId;
+
+/// Moves on to the next image on a timer. The timer only starts once an image is on
+/// screen, and the next image has to be loaded before it is switched to.
+pub struct Slideshow {
+    pub running: bool,
+    interval: Duration,
+    looping: bool,
+    shuffle: bool,
+    /// When the current image went up, or when the user last did something.
+    shown_at: Option<Instant>,
+    /// The images not shown yet in this round when shuffling, the next one last.
+    /// `None` until the first round is dealt.
+    remaining: Option<Vec<EntityId>>,
+}
+
+impl Slideshow {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            running: config.slideshow,
+            interval: Duration::from_secs_f32(config.slideshow_interval.max(0.1)),
+            looping: config.slideshow_loop,
+            shuffle: config.slideshow_shuffle,
+            shown_at: None,
+            remaining: None,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.running = !self.running;
+        self.shown_at = None;
+        self.remaining = None;
+    }
+
+    /// Starts the wait over, so the user gets a full interval after doing something.
+    pub const fn delay(&mut self, now: Instant) {
+        if self.shown_at.is_some() {
+            self.shown_at = Some(now);
+        }
+    }
+
+    /// The index to show after `selected` out of `order`, `None` at the end without looping.
+    pub fn upcoming(&mut self, selected: usize, order: &[EntityId]) -> Option<usize> {
+        if !self.running || order.len() < 2 {
+            return None;
+        }
+        if !self.shuffle {
+            return if selected + 1 < order.len() {
+                Some(selected + 1)
+            } else if self.looping {
+                Some(0)
+            } else {
+                None
+            };
+        }
+        loop {
+            if self.remaining.as_ref().is_none_or(Vec::is_empty) {
+                if self.remaining.is_some() && !self.looping {
+                    return None;
+                }
+                let current = order.get(selected);
+                let mut round: Vec<EntityId> = order
+                    .iter()
+                    .filter(|id| Some(*id) != current)
+                    .copied()
+                    .collect();
+                fastrand::shuffle(&mut round);
+                self.remaining = Some(round);
+            }
+            let remaining = self.remaining.as_mut()?;
+            let next = *remaining.last()?;
+            if let Some(index) = order.iter().position(|id| *id == next) {
+                return Some(index);
+            }
+            // Removed from disk since it was dealt.
+            remaining.pop();
+        }
+    }
+
+    /// Whether it is time to switch to the upcoming image. `shown` tells if the current
+    /// image is on screen yet and `ready` if the upcoming one is loaded.
+    pub fn advance(&mut self, shown: bool, ready: bool, now: Instant) -> bool {
+        if !self.running {
+            return false;
+        }
+        let Some(shown_at) = self.shown_at else {
+            if shown {
+                self.shown_at = Some(now);
+            }
+            return false;
+        };
+        if !ready || now.duration_since(shown_at) < self.interval {
+            return false;
+        }
+        self.shown_at = None;
+        if let Some(remaining) = &mut self.remaining {
+            remaining.pop();
+        }
+        true
+    }
+
+    /// Stops once there is nothing left to show.
+    pub fn finish(&mut self) {
+        if self.running {
+            println!("Slideshow finished");
+            self.toggle();
+        }
+    }
+}
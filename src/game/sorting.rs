@@ -0,0 +1,246 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use image::ImageReader;
+use strum_macros::{Display, EnumIter, EnumString};
+
+use crate::game::world::EntityId;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, EnumString, EnumIter)]
+#[strum(serialize_all = "snake_case")]
+pub enum SortKey {
+    /// By file name, with runs of digits compared as numbers and case ignored.
+    Name,
+    /// By modification time.
+    Modified,
+    /// By file size.
+    Size,
+    /// By extension, then by name.
+    Extension,
+    /// By pixel count.
+    Dimensions,
+    /// By the date the photo was taken, from its EXIF data.
+    Date,
+    /// Shuffled.
+    Random,
+    /// In whatever order the file system lists them.
+    Unsorted,
+}
+
+impl SortKey {
+    /// Whether images are already in this order as scanned, or as added by the watcher.
+    pub fn is_scan_order(self, order: SortOrder) -> bool {
+        self == Self::Unsorted || (self == Self::Name && order == SortOrder::Ascending)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, EnumString)]
+pub enum SortOrder {
+    #[strum(to_string = "asc", serialize = "ascending")]
+    Ascending,
+    #[strum(to_string = "desc", serialize = "descending")]
+    Descending,
+}
+
+impl SortOrder {
+    pub const fn reversed(self) -> Self {
+        match self {
+            Self::Ascending => Self::Descending,
+            Self::Descending => Self::Ascending,
+        }
+    }
+}
+
+/// What an image is compared by. Numbers and text never get compared with each other,
+/// since every image in one sort uses the same key.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum SortValue {
+    Number(u64),
+    Text(String),
+}
+
+/// Sorts the images within each directory, directories stay in the order they were
+/// scanned in so jumping between them still works. A random order shuffles everything.
+///
+/// Images the key can't be read for, like photos without EXIF data, go last.
+pub fn sort(
+    mut entries: Vec<(EntityId, PathBuf)>,
+    key: SortKey,
+    order: SortOrder,
+) -> Vec<EntityId> {
+    match key {
+        SortKey::Unsorted => {}
+        SortKey::Random => fastrand::shuffle(&mut entries),
+        _ => {
+            let mut dirs: HashMap<&Path, usize> = HashMap::new();
+            let mut keyed: Vec<(usize, Option<SortValue>, &(EntityId, PathBuf))> = entries
+                .iter()
+                .map(|entry| {
+                    let dir = entry.1.parent().unwrap_or_else(|| Path::new(""));
+                    let next_index = dirs.len();
+                    let dir_index = *dirs.entry(dir).or_insert(next_index);
+                    (dir_index, sort_value(key, &entry.1), entry)
+                })
+                .collect();
+            keyed.sort_by(|a, b| {
+                let by_name = || compare_names(&a.2 .1, &b.2 .1);
+                let ordering = match (&a.1, &b.1) {
+                    (Some(a_value), Some(b_value)) => {
+                        let ordering = a_value.cmp(b_value).then_with(by_name);
+                        match order {
+                            SortOrder::Ascending => ordering,
+                            SortOrder::Descending => ordering.reverse(),
+                        }
+                    }
+                    (Some(_), None) => Ordering::Less,
+                    (None, Some(_)) => Ordering::Greater,
+                    (None, None) => by_name(),
+                };
+                a.0.cmp(&b.0).then(ordering)
+            });
+            return keyed.into_iter().map(|(_, _, (id, _))| *id).collect();
+        }
+    }
+    entries.into_iter().map(|(id, _)| id).collect()
+}
+
+fn sort_value(key: SortKey, path: &Path) -> Option<SortValue> {
+    match key {
+        SortKey::Name => Some(SortValue::Text(String::new())),
+        SortKey::Modified => {
+            let modified = path.metadata().ok()?.modified().ok()?;
+            let since_epoch = modified.duration_since(SystemTime::UNIX_EPOCH).ok()?;
+            Some(SortValue::Number(since_epoch.as_secs()))
+        }
+        SortKey::Size => Some(SortValue::Number(path.metadata().ok()?.len())),
+        SortKey::Extension => {
+            let extension = path.extension()?.to_string_lossy().to_lowercase();
+            Some(SortValue::Text(extension))
+        }
+        SortKey::Dimensions => {
+            let (width, height) = ImageReader::open(path)
+                .ok()?
+                .with_guessed_format()
+                .ok()?
+                .into_dimensions()
+                .ok()?;
+            Some(SortValue::Number(u64::from(width) * u64::from(height)))
+        }
+        SortKey::Date => exif_date(path).map(SortValue::Text),
+        SortKey::Random | SortKey::Unsorted => None,
+    }
+}
+
+/// The "YYYY:MM:DD HH:MM:SS" the photo was taken at, which sorts correctly as text.
+fn exif_date(path: &Path) -> Option<String> {
+    let mut reader = BufReader::new(File::open(path).ok()?);
+    let exif = exif::Reader::new().read_from_container(&mut reader).ok()?;
+    [exif::Tag::DateTimeOriginal, exif::Tag::DateTime]
+        .iter()
+        .find_map(|tag| exif.get_field(*tag, exif::In::PRIMARY))
+        .map(|field| field.display_value().to_string())
+}
+
+/// Compares file names the way people count, so "img2" comes before "img10".
+/// Case is ignored unless the names are otherwise the same.
+pub fn compare_names(a: &Path, b: &Path) -> Ordering {
+    let a_name = a.file_name().unwrap_or(a.as_os_str()).to_string_lossy();
+    let b_name = b.file_name().unwrap_or(b.as_os_str()).to_string_lossy();
+    natural_cmp(&a_name, &b_name).then_with(|| a_name.cmp(&b_name))
+}
+
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let x = take_number(&mut a);
+                let y = take_number(&mut b);
+                // Without leading zeros, the longer number is the bigger one.
+                let ordering = x
+                    .trim_start_matches('0')
+                    .len()
+                    .cmp(&y.trim_start_matches('0').len())
+                    .then_with(|| x.trim_start_matches('0').cmp(y.trim_start_matches('0')))
+                    .then_with(|| x.len().cmp(&y.len()));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(x), Some(y)) => {
+                let ordering = x.to_lowercase().cmp(y.to_lowercase());
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+fn take_number(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut number = String::new();
+    while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+        number.push(digit);
+    }
+    number
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(a: &str, b: &str) -> Ordering {
+        compare_names(Path::new(a), Path::new(b))
+    }
+
+    #[test]
+    fn numbers_compare_by_value() {
+        assert_eq!(natural_cmp("img2", "img10"), Ordering::Less);
+        assert_eq!(natural_cmp("img10", "img2"), Ordering::Greater);
+        assert_eq!(natural_cmp("img2b", "img2a"), Ordering::Greater);
+        assert_eq!(natural_cmp("1000", "999"), Ordering::Greater);
+    }
+
+    #[test]
+    fn leading_zeros_only_break_ties() {
+        assert_eq!(natural_cmp("a01", "a1"), Ordering::Greater);
+        assert_eq!(natural_cmp("a01", "a2"), Ordering::Less);
+        assert_eq!(natural_cmp("a001", "a01"), Ordering::Greater);
+    }
+
+    #[test]
+    fn case_only_breaks_ties() {
+        assert_eq!(names("a.png", "B.png"), Ordering::Less);
+        assert_eq!(names("B.png", "c.png"), Ordering::Less);
+        assert_eq!(natural_cmp("A.png", "a.png"), Ordering::Equal);
+        assert_eq!(names("A.png", "a.png"), Ordering::Less);
+    }
+
+    #[test]
+    fn names_ignore_the_directory() {
+        assert_eq!(names("z/img2.png", "a/img10.png"), Ordering::Less);
+    }
+
+    #[test]
+    fn directories_keep_their_scan_order() {
+        let entries = ["b/2.png", "a/10.png", "b/1.png", "a/9.png"]
+            .iter()
+            .enumerate()
+            .map(|(id, path)| (id, PathBuf::from(path)))
+            .collect::<Vec<_>>();
+        let ascending = sort(entries.clone(), SortKey::Name, SortOrder::Ascending);
+        assert_eq!(ascending, [2, 0, 3, 1]);
+        let descending = sort(entries, SortKey::Name, SortOrder::Descending);
+        assert_eq!(descending, [0, 2, 1, 3]);
+    }
+}
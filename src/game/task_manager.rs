@@ -1,74 +1,218 @@
-use std::collections::VecDeque;
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::thread;
+use std::time::Duration;
 
 use crossbeam_channel::{unbounded, Receiver, Sender};
 use glam::UVec2;
-use image::ImageReader;
+use image::codecs::{gif::GifDecoder, png::PngDecoder, webp::WebPDecoder};
+use image::{
+    metadata::Orientation, AnimationDecoder, DynamicImage, Frames, ImageDecoder, ImageFormat,
+    ImageReader, ImageResult, RgbaImage,
+};
 
-use crate::game::world::{EntityId, World};
+use crate::config::Config;
+use crate::game::filters::{self, Filter};
+use crate::game::scanner;
+use crate::game::sorting::{self, SortKey, SortOrder};
+use crate::game::task_queue::{Priority, TaskQueue};
+use crate::game::thumbnail_cache;
+use crate::game::world::{EntityId, LoadState, World, FRAME_CHUNK};
 use speedy2d::{
-    image::{ImageDataType, ImageSmoothingMode},
+    image::{ImageDataType, ImageHandle, ImageSmoothingMode},
     Graphics2D,
 };
 
+/// Browsers treat GIF delays this short as "as fast as possible" and slow them down, so do we.
+const MIN_FRAME_DELAY: Duration = Duration::from_millis(20);
+const DEFAULT_FRAME_DELAY: Duration = Duration::from_millis(100);
+
 enum Task {
-    LoadImage { id: EntityId, path: PathBuf },
+    LoadImage {
+        id: EntityId,
+        path: PathBuf,
+        filters: Vec<Filter>,
+        /// Where to start in an animation, it decodes [`FRAME_CHUNK`] frames from there.
+        first_frame: usize,
+        /// Asks an animation's decoder for the chunks after the first.
+        resume: Receiver<()>,
+    },
+    ApplyFilters {
+        id: EntityId,
+        pixels: Arc<Vec<u8>>,
+        size: UVec2,
+        filters: Vec<Filter>,
+    },
+    Scan {
+        config: Box<Config>,
+    },
+    Sort {
+        entries: Vec<(EntityId, PathBuf)>,
+        key: SortKey,
+        order: SortOrder,
+    },
+    Thumbnail {
+        id: EntityId,
+        path: PathBuf,
+        size: u32,
+    },
 }
 
 enum TaskResult {
     DecodedImage {
         id: EntityId,
+        /// Unfiltered pixels, kept so filters can be changed without reading the file again.
+        pixels: Arc<Vec<u8>>,
+        /// Pixels with `filters` applied, `None` when there are no filters.
+        filtered: Option<Vec<u8>>,
+        size: UVec2,
+        filters: Vec<Filter>,
+        /// What the file turned out to hold, `None` when only the filters were applied again.
+        format: Option<ImageFormat>,
+    },
+    /// One frame of an animation, sent as soon as it is decoded.
+    DecodedFrame {
+        id: EntityId,
+        index: usize,
         bytes: Vec<u8>,
-        width: u32,
-        height: u32,
+        size: UVec2,
+        delay: Duration,
+        filters: Vec<Filter>,
+        format: ImageFormat,
     },
+    /// A chunk of animation frames is done, `frame_count` is set if it reached the end.
+    /// The decoder carries on from `next_frame` when resumed, after the end that's 0 again.
+    FramesDone {
+        id: EntityId,
+        frame_count: Option<usize>,
+        next_frame: usize,
+    },
+    /// The file couldn't be read or decoded.
+    Failed {
+        id: EntityId,
+        error: String,
+    },
+    /// An image found by a running scan.
+    Found {
+        path: PathBuf,
+    },
+    ScanFinished,
+    Sorted {
+        order: Vec<EntityId>,
+    },
+    Thumbnail {
+        id: EntityId,
+        bytes: Vec<u8>,
+        size: UVec2,
+    },
+    ThumbnailFailed {
+        id: EntityId,
+        error: String,
+    },
+}
+
+/// An animation decoder waiting between chunks on a thread of its own, since the frames
+/// decode in order and the decoder can't be handed from one worker to the next.
+struct FrameStream {
+    /// Dropping it stops the decoder.
+    resume: Sender<()>,
+    next_frame: usize,
 }
 
 pub struct TaskManager {
     thread_count: u8,
 
-    sender: Sender<Task>,
+    tasks: Arc<TaskQueue<Task>>,
     result_receiver: Receiver<TaskResult>,
     queue: VecDeque<EntityId>,
+    thumbnail_queue: HashSet<EntityId>,
+    streams: HashMap<EntityId, FrameStream>,
+    scanning: bool,
+    /// Set when a scan finishes, until taken.
+    scan_finished: bool,
+    sorting: bool,
 }
 
 impl TaskManager {
-    pub fn new(thread_count: u8) -> Self {
-        let (task_sender, task_receiver) = unbounded::<Task>();
+    /// With `auto_orient`, stills are rotated and flipped the way their EXIF data says.
+    /// With `thumbnail_cache`, thumbnails are shared with other programs on disk.
+    pub fn new(thread_count: u8, auto_orient: bool, thumbnail_cache: bool) -> Self {
+        let tasks = Arc::new(TaskQueue::<Task>::new());
         let (result_sender, result_receiver) = unbounded::<TaskResult>();
 
-        let task_receiver = Arc::new(task_receiver);
         let result_sender = Arc::new(result_sender);
 
         for _ in 0..thread_count {
-            let task_receiver = Arc::clone(&task_receiver);
+            let tasks = Arc::clone(&tasks);
             let result_sender = Arc::clone(&result_sender);
 
             thread::spawn(move || {
-                while let Ok(task) = task_receiver.recv() {
+                while let Some(task) = tasks.pop() {
                     match task {
-                        Task::LoadImage { id, path } => {
-                            if let Ok(reader) = ImageReader::open(&path) {
-                                let image = reader.decode();
-                                match image {
-                                    Ok(image) => {
-                                        // Convert to raw RGBA bytes
-                                        let rgba = image.to_rgba8();
-                                        let (width, height) = rgba.dimensions();
-                                        let bytes = rgba.into_raw();
-
-                                        let _ = result_sender.send(TaskResult::DecodedImage {
-                                            id,
-                                            bytes,
-                                            width,
-                                            height,
-                                        });
-                                    }
-                                    Err(err) => {
-                                        eprintln!("Failed to decode image: {err}");
-                                    }
+                        Task::LoadImage {
+                            id,
+                            path,
+                            filters,
+                            first_frame,
+                            resume,
+                        } => {
+                            let decoded = Self::decode(
+                                id,
+                                &path,
+                                filters,
+                                first_frame,
+                                resume,
+                                auto_orient,
+                                &result_sender,
+                            );
+                            if let Err(err) = decoded {
+                                eprintln!("Failed to load {}: {err}", path.display());
+                                let error = err.to_string();
+                                let _ = result_sender.send(TaskResult::Failed { id, error });
+                            }
+                        }
+                        Task::ApplyFilters {
+                            id,
+                            pixels,
+                            size,
+                            filters,
+                        } => {
+                            let filtered = Self::filter(id, pixels, size, filters, None);
+                            let _ = result_sender.send(filtered);
+                        }
+                        Task::Scan { config } => {
+                            scanner::scan(&config, &mut |path| {
+                                let _ = result_sender.send(TaskResult::Found { path });
+                            });
+                            let _ = result_sender.send(TaskResult::ScanFinished);
+                        }
+                        Task::Sort {
+                            entries,
+                            key,
+                            order,
+                        } => {
+                            let order = sorting::sort(entries, key, order);
+                            let _ = result_sender.send(TaskResult::Sorted { order });
+                        }
+                        Task::Thumbnail { id, path, size } => {
+                            match Self::make_thumbnail(&path, size, auto_orient, thumbnail_cache) {
+                                Ok((bytes, size)) => {
+                                    let _ = result_sender.send(TaskResult::Thumbnail {
+                                        id,
+                                        bytes,
+                                        size,
+                                    });
+                                }
+                                Err(err) => {
+                                    eprintln!(
+                                        "Failed to make a thumbnail of {}: {err}",
+                                        path.display()
+                                    );
+                                    let error = err.to_string();
+                                    let _ = result_sender
+                                        .send(TaskResult::ThumbnailFailed { id, error });
                                 }
                             }
                         }
@@ -79,48 +223,784 @@ impl TaskManager {
 
         Self {
             thread_count,
-            sender: task_sender,
+            tasks,
             result_receiver,
             queue: VecDeque::new(),
+            thumbnail_queue: HashSet::new(),
+            streams: HashMap::new(),
+            scanning: false,
+            scan_finished: false,
+            sorting: false,
+        }
+    }
+
+    fn decode(
+        id: EntityId,
+        path: &Path,
+        filters: Vec<Filter>,
+        first_frame: usize,
+        resume: Receiver<()>,
+        auto_orient: bool,
+        result_sender: &Sender<TaskResult>,
+    ) -> ImageResult<()> {
+        // The content decides the format, the extension may be wrong or missing.
+        let reader = ImageReader::open(path)?.with_guessed_format()?;
+        let format = reader.format();
+        let image = match format {
+            Some(ImageFormat::Gif) => {
+                Self::start_stream(
+                    id,
+                    path,
+                    ImageFormat::Gif,
+                    filters,
+                    first_frame,
+                    resume,
+                    result_sender,
+                );
+                return Ok(());
+            }
+            Some(ImageFormat::Png) => {
+                let decoder = PngDecoder::new(reader.into_inner())?;
+                if decoder.is_apng()? {
+                    Self::start_stream(
+                        id,
+                        path,
+                        ImageFormat::Png,
+                        filters,
+                        first_frame,
+                        resume,
+                        result_sender,
+                    );
+                    return Ok(());
+                }
+                Self::decode_still(decoder, auto_orient)?
+            }
+            Some(ImageFormat::WebP) => {
+                let decoder = WebPDecoder::new(reader.into_inner())?;
+                if decoder.has_animation() {
+                    Self::start_stream(
+                        id,
+                        path,
+                        ImageFormat::WebP,
+                        filters,
+                        first_frame,
+                        resume,
+                        result_sender,
+                    );
+                    return Ok(());
+                }
+                Self::decode_still(decoder, auto_orient)?
+            }
+            _ => Self::decode_still(reader.into_decoder()?, auto_orient)?,
+        };
+
+        // Convert to raw RGBA bytes
+        let rgba = image.to_rgba8();
+        let (width, height) = rgba.dimensions();
+        let pixels = Arc::new(rgba.into_raw());
+
+        let size = UVec2::new(width, height);
+        let _ = result_sender.send(Self::filter(id, pixels, size, filters, format));
+        Ok(())
+    }
+
+    /// Photos are often stored sideways with an EXIF tag saying how to turn them upright.
+    fn decode_still(
+        mut decoder: impl ImageDecoder,
+        auto_orient: bool,
+    ) -> ImageResult<DynamicImage> {
+        // A broken EXIF block is no reason not to show the image, just upright as stored.
+        let orientation = if auto_orient {
+            Some(decoder.orientation().unwrap_or(Orientation::NoTransforms))
+        } else {
+            None
+        };
+        let mut image = DynamicImage::from_decoder(decoder)?;
+        if let Some(orientation) = orientation {
+            image.apply_orientation(orientation);
+        }
+        Ok(image)
+    }
+
+    /// Decodes the first frame and shrinks it here, so only a small texture is uploaded.
+    /// Thumbnails are made at the size of the cache folder they go in.
+    fn make_thumbnail(
+        path: &Path,
+        size: u32,
+        auto_orient: bool,
+        cache: bool,
+    ) -> ImageResult<(Vec<u8>, UVec2)> {
+        let cached = if cache {
+            thumbnail_cache::load(path, size)
+        } else {
+            None
+        };
+        let rgba = match cached {
+            Some(rgba) => rgba,
+            None => Self::fresh_thumbnail(path, size, auto_orient, cache)?,
+        };
+        let (width, height) = rgba.dimensions();
+        Ok((rgba.into_raw(), UVec2::new(width, height)))
+    }
+
+    fn fresh_thumbnail(
+        path: &Path,
+        size: u32,
+        auto_orient: bool,
+        cache: bool,
+    ) -> ImageResult<RgbaImage> {
+        let decoder = ImageReader::open(path)?
+            .with_guessed_format()?
+            .into_decoder()?;
+        let image = Self::decode_still(decoder, auto_orient)?;
+        let (_, size) = thumbnail_cache::folder_for(size);
+        let rgba = if image.width() <= size && image.height() <= size {
+            image.to_rgba8()
+        } else {
+            image.thumbnail(size, size).to_rgba8()
+        };
+        if cache {
+            if let Err(err) = thumbnail_cache::save(path, size, &rgba) {
+                eprintln!("Failed to store the thumbnail of {}: {err}", path.display());
+            }
+        }
+        Ok(rgba)
+    }
+
+    /// Starts decoding an animation on a thread of its own, see [`FrameStream`].
+    fn start_stream(
+        id: EntityId,
+        path: &Path,
+        format: ImageFormat,
+        filters: Vec<Filter>,
+        first_frame: usize,
+        resume: Receiver<()>,
+        result_sender: &Sender<TaskResult>,
+    ) {
+        let path = path.to_path_buf();
+        let result_sender = result_sender.clone();
+        thread::spawn(move || {
+            let streamed = Self::stream_frames(
+                id,
+                &path,
+                format,
+                &filters,
+                first_frame,
+                &resume,
+                &result_sender,
+            );
+            if let Err(err) = streamed {
+                eprintln!("Failed to load {}: {err}", path.display());
+                let error = err.to_string();
+                let _ = result_sender.send(TaskResult::Failed { id, error });
+            }
+        });
+    }
+
+    fn open_frames(path: &Path, format: ImageFormat) -> ImageResult<Frames<'static>> {
+        let reader = ImageReader::open(path)?.into_inner();
+        Ok(match format {
+            ImageFormat::Gif => GifDecoder::new(reader)?.into_frames(),
+            ImageFormat::Png => PngDecoder::new(reader)?.apng()?.into_frames(),
+            _ => WebPDecoder::new(reader)?.into_frames(),
+        })
+    }
+
+    /// Sends the frames from `first_frame` on one by one as they decode, so long animations
+    /// start playing right away. Only a chunk is decoded at a time, then it waits for `resume`
+    /// as playback gets there, so long animations never have to be held in memory as a whole.
+    /// Resumed after the end, it starts over from the first frame.
+    fn stream_frames(
+        id: EntityId,
+        path: &Path,
+        format: ImageFormat,
+        filters: &[Filter],
+        mut first_frame: usize,
+        resume: &Receiver<()>,
+        result_sender: &Sender<TaskResult>,
+    ) -> ImageResult<()> {
+        let send_frame = |index, frame: image::Frame| {
+            let delay = match Duration::from(frame.delay()) {
+                delay if delay < MIN_FRAME_DELAY => DEFAULT_FRAME_DELAY,
+                delay => delay,
+            };
+            let rgba = frame.into_buffer();
+            let size = UVec2::new(rgba.width(), rgba.height());
+            let mut bytes = rgba.into_raw();
+            filters::apply_all(filters, &mut bytes);
+            result_sender
+                .send(TaskResult::DecodedFrame {
+                    id,
+                    index,
+                    bytes,
+                    size,
+                    delay,
+                    filters: filters.to_vec(),
+                    format,
+                })
+                .is_ok()
+        };
+
+        loop {
+            let mut frames = Self::open_frames(path, format)?.peekable();
+            let mut index = 0;
+            let mut frame_count = None;
+            // Frames only decode in order, the ones before `first_frame` are decoded and dropped.
+            while index < first_frame {
+                if frames.next().transpose()?.is_none() {
+                    frame_count = Some(index);
+                    break;
+                }
+                index += 1;
+            }
+            let mut pending = if first_frame == 0 {
+                let Some(first) = frames.next().transpose()? else {
+                    return Ok(());
+                };
+                if frames.peek().is_none() {
+                    // A single frame, treat it as a still image so it keeps its unfiltered pixels.
+                    let rgba = first.into_buffer();
+                    let size = UVec2::new(rgba.width(), rgba.height());
+                    let pixels = Arc::new(rgba.into_raw());
+                    let still = Self::filter(id, pixels, size, filters.to_vec(), Some(format));
+                    let _ = result_sender.send(still);
+                    return Ok(());
+                }
+                Some(first)
+            } else {
+                None
+            };
+
+            loop {
+                let mut result = Ok(());
+                let chunk_end = index + FRAME_CHUNK;
+                while frame_count.is_none() && index < chunk_end {
+                    match pending.take().map(Ok).or_else(|| frames.next()) {
+                        Some(Ok(frame)) => {
+                            if !send_frame(index, frame) {
+                                return Ok(());
+                            }
+                            index += 1;
+                        }
+                        Some(Err(err)) => {
+                            // Keep the frames decoded so far playing.
+                            result = Err(err);
+                            break;
+                        }
+                        None => frame_count = Some(index),
+                    }
+                }
+                let next_frame = if frame_count.is_some() { 0 } else { index };
+                let _ = result_sender.send(TaskResult::FramesDone {
+                    id,
+                    frame_count,
+                    next_frame,
+                });
+                result?;
+                // The manager dropped the stream, the image isn't looked at anymore.
+                if resume.recv().is_err() {
+                    return Ok(());
+                }
+                if frame_count.is_some() {
+                    break;
+                }
+            }
+            first_frame = 0;
+        }
+    }
+
+    fn filter(
+        id: EntityId,
+        pixels: Arc<Vec<u8>>,
+        size: UVec2,
+        filters: Vec<Filter>,
+        format: Option<ImageFormat>,
+    ) -> TaskResult {
+        let filtered = if filters.is_empty() {
+            None
+        } else {
+            let mut filtered = pixels.as_ref().clone();
+            filters::apply_all(&filters, &mut filtered);
+            Some(filtered)
+        };
+        TaskResult::DecodedImage {
+            id,
+            pixels,
+            filtered,
+            size,
+            filters,
+            format,
+        }
+    }
+
+    pub fn load(
+        &mut self,
+        id: EntityId,
+        path: PathBuf,
+        filters: &[Filter],
+        first_frame: usize,
+        priority: Priority,
+    ) {
+        if self.queue.contains(&id) {
+            return;
+        }
+        let filters = filters.to_vec();
+        let (resume_sender, resume) = unbounded();
+        let stream = FrameStream {
+            resume: resume_sender,
+            next_frame: first_frame,
+        };
+        // Stops an older decoder of this image, if any.
+        self.streams.insert(id, stream);
+        self.tasks.push(
+            priority,
+            Task::LoadImage {
+                id,
+                path,
+                filters,
+                first_frame,
+                resume,
+            },
+        );
+        self.queue.push_back(id);
+    }
+
+    /// Asks for the animation frames from `first_frame` on. A decoder waiting right there
+    /// carries on, otherwise a new one starts and decodes its way to `first_frame`.
+    pub fn load_frames(
+        &mut self,
+        id: EntityId,
+        path: PathBuf,
+        filters: &[Filter],
+        first_frame: usize,
+    ) {
+        if self.queue.contains(&id) {
+            return;
+        }
+        if let Some(stream) = self.streams.get(&id) {
+            if stream.next_frame == first_frame && stream.resume.send(()).is_ok() {
+                self.queue.push_back(id);
+                return;
+            }
+        }
+        self.load(id, path, filters, first_frame, Priority::Visible);
+    }
+
+    /// Makes a thumbnail at most `size` pixels wide and high, behind all other tasks.
+    /// A file that fails is marked failed and not tried again until it changes.
+    pub fn thumbnail(&mut self, id: EntityId, path: PathBuf, size: u32) {
+        if !self.thumbnail_queue.insert(id) {
+            return;
+        }
+        self.tasks
+            .push(Priority::Thumbnail, Task::Thumbnail { id, path, size });
+    }
+
+    /// Re-ranks the waiting image tasks by what is wanted now, so skipping through images
+    /// doesn't leave a backlog of ones no longer looked at. Images other than `visible` and
+    /// `neighbors`, and thumbnails other than `thumbnails`, are dropped from the queue, and
+    /// the decoders of their animations are stopped.
+    pub fn prioritize(
+        &mut self,
+        visible: Option<EntityId>,
+        neighbors: &[EntityId],
+        thumbnails: &[EntityId],
+    ) {
+        let cancelled = self.tasks.reprioritize(|task| match task {
+            Task::LoadImage { id, .. } | Task::ApplyFilters { id, .. } => {
+                if Some(*id) == visible {
+                    Some(Priority::Visible)
+                } else if neighbors.contains(id) {
+                    Some(Priority::Neighbor)
+                } else {
+                    None
+                }
+            }
+            Task::Thumbnail { id, .. } => thumbnails.contains(id).then_some(Priority::Thumbnail),
+            Task::Scan { .. } | Task::Sort { .. } => Some(Priority::Visible),
+        });
+        for task in cancelled {
+            match task {
+                Task::LoadImage { id, .. } | Task::ApplyFilters { id, .. } => {
+                    self.queue.retain(|queued_id| *queued_id != id);
+                }
+                Task::Thumbnail { id, .. } => {
+                    self.thumbnail_queue.remove(&id);
+                }
+                Task::Scan { .. } | Task::Sort { .. } => {}
+            }
         }
+        self.streams
+            .retain(|id, _| Some(*id) == visible || neighbors.contains(id));
+    }
+
+    /// Scans the configured inputs in the background, found images are added to the world
+    /// as they come in.
+    pub fn scan(&mut self, config: Config) {
+        let config = Box::new(config);
+        // Nothing can be shown before the scan finds it.
+        self.tasks.push(Priority::Visible, Task::Scan { config });
+        self.scanning = true;
+    }
+
+    /// Sorts in the background, since some keys need every file to be opened.
+    pub fn sort(&mut self, entries: Vec<(EntityId, PathBuf)>, key: SortKey, order: SortOrder) {
+        self.tasks.push(
+            Priority::Visible,
+            Task::Sort {
+                entries,
+                key,
+                order,
+            },
+        );
+        self.sorting = true;
     }
 
-    pub fn load(&mut self, id: EntityId, path: PathBuf) {
+    /// Re-runs the filter stage on already decoded pixels.
+    pub fn apply_filters(
+        &mut self,
+        id: EntityId,
+        pixels: Arc<Vec<u8>>,
+        size: UVec2,
+        filters: &[Filter],
+        priority: Priority,
+    ) {
         if self.queue.contains(&id) {
             return;
         }
-        let _ = self.sender.send(Task::LoadImage { id, path });
+        let filters = filters.to_vec();
+        self.tasks.push(
+            priority,
+            Task::ApplyFilters {
+                id,
+                pixels,
+                size,
+                filters,
+            },
+        );
         self.queue.push_back(id);
     }
 
-    pub fn update(&mut self, world: &mut World, graphics: &mut Graphics2D) {
+    /// Applies finished tasks to the world, returns the images found by the scan since last time.
+    pub fn update(&mut self, world: &mut World, graphics: &mut Graphics2D) -> Vec<EntityId> {
+        let mut found = Vec::new();
         while let Ok(result) = self.result_receiver.try_recv() {
             match result {
                 TaskResult::DecodedImage {
                     id,
+                    pixels,
+                    filtered,
+                    size,
+                    filters,
+                    format,
+                } => {
+                    match Self::upload(graphics, size, filtered.as_ref().unwrap_or(&pixels)) {
+                        Ok(image) => {
+                            world.insert_image(id, image, pixels, filters);
+                            if let Some(format) = format {
+                                world.set_format(id, format);
+                            }
+                        }
+                        Err(err) => {
+                            // Not the old texture either, its filters would be asked for again.
+                            world.drop_image(id);
+                            world.set_failed(id, Self::upload_failed(world, id, &err));
+                        }
+                    }
+                    self.streams.remove(&id);
+                    self.queue.retain(|queued_id| *queued_id != id);
+                }
+                TaskResult::DecodedFrame {
+                    id,
+                    index,
                     bytes,
-                    width,
-                    height,
+                    size,
+                    delay,
+                    filters,
+                    format,
+                } => {
+                    match Self::upload(graphics, size, &bytes) {
+                        Ok(image) => {
+                            world.insert_frame(id, index, image, delay, filters);
+                            world.set_format(id, format);
+                        }
+                        Err(err) => {
+                            // Ends the animation at the frames uploaded so far.
+                            world.set_failed(id, Self::upload_failed(world, id, &err));
+                            self.streams.remove(&id);
+                        }
+                    }
+                }
+                TaskResult::FramesDone {
+                    id,
+                    frame_count,
+                    next_frame,
                 } => {
-                    if let Ok(image) = graphics.create_image_from_raw_pixels(
-                        ImageDataType::RGBA,
-                        ImageSmoothingMode::Linear,
-                        UVec2::new(width, height),
-                        &bytes,
-                    ) {
-                        world.insert_image(id, image);
+                    if let Some(frame_count) = frame_count {
+                        world.set_frame_count(id, frame_count);
                     }
+                    if let Some(stream) = self.streams.get_mut(&id) {
+                        stream.next_frame = next_frame;
+                    }
+                    self.queue.retain(|queued_id| *queued_id != id);
+                }
+                TaskResult::Failed { id, error } => {
+                    world.set_failed(id, error);
+                    self.streams.remove(&id);
                     self.queue.retain(|queued_id| *queued_id != id);
                 }
+                TaskResult::Found { path } => {
+                    found.extend(world.spawn_asset(path));
+                }
+                TaskResult::ScanFinished => {
+                    self.scanning = false;
+                    self.scan_finished = true;
+                }
+                TaskResult::Sorted { order } => {
+                    world.reorder(order);
+                    self.sorting = false;
+                }
+                TaskResult::Thumbnail { id, bytes, size } => {
+                    match Self::upload(graphics, size, &bytes) {
+                        Ok(image) => {
+                            world.insert_thumbnail(id, image);
+                            self.thumbnail_queue.remove(&id);
+                        }
+                        Err(err) => {
+                            let error = Self::upload_failed(world, id, &err);
+                            self.thumbnail_failed(world, id, error);
+                        }
+                    }
+                }
+                TaskResult::ThumbnailFailed { id, error } => {
+                    self.thumbnail_failed(world, id, error);
+                }
             }
         }
+        found
+    }
+
+    fn upload(graphics: &mut Graphics2D, size: UVec2, bytes: &[u8]) -> Result<ImageHandle, String> {
+        graphics
+            .create_image_from_raw_pixels(
+                ImageDataType::RGBA,
+                ImageSmoothingMode::Linear,
+                size,
+                bytes,
+            )
+            .map_err(|err| err.to_string())
+    }
+
+    /// Marks the image failed, so the gallery shows it as broken. It is tried again once
+    /// the file changes and the failure is dropped. An image that did load keeps its place
+    /// in the queue instead, so its thumbnail isn't tried over and over.
+    fn thumbnail_failed(&mut self, world: &mut World, id: EntityId, error: String) {
+        world.set_failed(id, error);
+        if let LoadState::Failed(_) = world.load_state(id) {
+            self.thumbnail_queue.remove(&id);
+        }
+    }
+
+    fn upload_failed(world: &World, id: EntityId, err: &impl Display) -> String {
+        if let Some(path) = world.get_path(id) {
+            eprintln!("Failed to upload {}: {err}", path.display());
+        }
+        format!("Failed to upload the image: {err}")
     }
 
     pub fn is_idle(&self) -> bool {
         self.queue.is_empty()
     }
 
+    pub const fn is_scanning(&self) -> bool {
+        self.scanning
+    }
+
+    /// Whether a scan finished since last asked.
+    pub fn take_scan_finished(&mut self) -> bool {
+        std::mem::take(&mut self.scan_finished)
+    }
+
+    pub const fn is_sorting(&self) -> bool {
+        self.sorting
+    }
+
     pub const fn threads(&self) -> u8 {
         self.thread_count
     }
 }
+
+impl Drop for TaskManager {
+    /// Lets the workers finish their current task and exit.
+    fn drop(&mut self) {
+        self.tasks.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::{env, fs};
+
+    use image::codecs::gif::GifEncoder;
+    use image::codecs::jpeg::{JpegDecoder, JpegEncoder};
+    use image::{imageops, GrayImage, Luma, Rgba};
+
+    use super::*;
+
+    /// Gray levels of the 8x8 blocks of the upright picture, three across and two down.
+    const BLOCKS: [[u8; 3]; 2] = [[0, 50, 100], [150, 200, 250]];
+
+    fn upright() -> GrayImage {
+        GrayImage::from_fn(24, 16, |x, y| {
+            Luma([BLOCKS[y as usize / 8][x as usize / 8]])
+        })
+    }
+
+    /// The upright picture stored the way a camera held in `orientation` would, as a JPEG
+    /// with an EXIF block saying how to turn it back.
+    fn fixture(orientation: u16) -> Vec<u8> {
+        let picture = upright();
+        let stored = match orientation {
+            1 => picture,
+            2 => imageops::flip_horizontal(&picture),
+            3 => imageops::rotate180(&picture),
+            4 => imageops::flip_vertical(&picture),
+            5 => imageops::flip_horizontal(&imageops::rotate90(&picture)),
+            6 => imageops::rotate270(&picture),
+            7 => imageops::flip_horizontal(&imageops::rotate270(&picture)),
+            8 => imageops::rotate90(&picture),
+            _ => unreachable!(),
+        };
+        let mut tiff = b"II*\0\x08\0\0\0\x01\0\x12\x01\x03\0\x01\0\0\0".to_vec();
+        tiff.extend_from_slice(&orientation.to_le_bytes());
+        tiff.extend_from_slice(&[0; 6]);
+        with_exif(&stored, &tiff)
+    }
+
+    fn with_exif(stored: &GrayImage, tiff: &[u8]) -> Vec<u8> {
+        let mut jpeg = Vec::new();
+        JpegEncoder::new_with_quality(&mut jpeg, 100)
+            .encode_image(stored)
+            .unwrap();
+        let mut app1 = vec![0xFF, 0xE1];
+        let length = u16::try_from(2 + 6 + tiff.len()).unwrap();
+        app1.extend_from_slice(&length.to_be_bytes());
+        app1.extend_from_slice(b"Exif\0\0");
+        app1.extend_from_slice(tiff);
+        // Right after the start of image marker.
+        jpeg.splice(2..2, app1);
+        jpeg
+    }
+
+    fn decode(jpeg: Vec<u8>, auto_orient: bool) -> GrayImage {
+        let decoder = JpegDecoder::new(Cursor::new(jpeg)).unwrap();
+        TaskManager::decode_still(decoder, auto_orient)
+            .unwrap()
+            .to_luma8()
+    }
+
+    #[test]
+    fn every_orientation_decodes_upright() {
+        for orientation in 1..=8 {
+            let image = decode(fixture(orientation), true);
+            assert_eq!(image.dimensions(), (24, 16), "orientation {orientation}");
+            for (row, levels) in (0..).zip(BLOCKS) {
+                for (column, level) in (0..).zip(levels) {
+                    let [found] = image.get_pixel(column * 8 + 4, row * 8 + 4).0;
+                    assert!(
+                        found.abs_diff(level) < 8,
+                        "orientation {orientation}, block {column},{row}: {found} instead of {level}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn orientation_is_ignored_without_auto_orient() {
+        let image = decode(fixture(6), false);
+        assert_eq!(image.dimensions(), (16, 24));
+    }
+
+    #[test]
+    fn broken_exif_still_decodes() {
+        let image = decode(with_exif(&upright(), b"MM\0*\xFF\xFF"), true);
+        assert_eq!(image.dimensions(), (24, 16));
+    }
+
+    /// A GIF of `frame_count` frames, each a different shade of red.
+    fn animation(name: &str, frame_count: usize) -> PathBuf {
+        let frames = (0..=u8::MAX)
+            .take(frame_count)
+            .map(|level| image::Frame::new(RgbaImage::from_pixel(4, 4, Rgba([level, 0, 0, 255]))));
+        let mut gif = Vec::new();
+        GifEncoder::new(&mut gif).encode_frames(frames).unwrap();
+        let path = env::temp_dir().join(format!("pfiew-{name}-{}.gif", std::process::id()));
+        fs::write(&path, gif).unwrap();
+        path
+    }
+
+    /// The frame indices of the next chunk, its frame count and where the stream carries on.
+    fn next_chunk(results: &Receiver<TaskResult>) -> (Vec<usize>, Option<usize>, usize) {
+        let mut indices = Vec::new();
+        loop {
+            match results.recv_timeout(Duration::from_secs(10)).unwrap() {
+                TaskResult::DecodedFrame { index, .. } => indices.push(index),
+                TaskResult::FramesDone {
+                    frame_count,
+                    next_frame,
+                    ..
+                } => return (indices, frame_count, next_frame),
+                _ => panic!("expected animation frames"),
+            }
+        }
+    }
+
+    #[test]
+    fn animation_streams_a_chunk_per_resume_and_starts_over() {
+        let frame_count = FRAME_CHUNK + FRAME_CHUNK / 2;
+        let path = animation("chunks", frame_count);
+        let (resume_sender, resume) = unbounded();
+        let (result_sender, results) = unbounded();
+        TaskManager::start_stream(
+            0,
+            &path,
+            ImageFormat::Gif,
+            Vec::new(),
+            0,
+            resume,
+            &result_sender,
+        );
+
+        let first_chunk = ((0..FRAME_CHUNK).collect(), None, FRAME_CHUNK);
+        assert_eq!(next_chunk(&results), first_chunk);
+        // Waits for playback to get there.
+        assert!(results.recv_timeout(Duration::from_millis(100)).is_err());
+        resume_sender.send(()).unwrap();
+        let last_chunk = ((FRAME_CHUNK..frame_count).collect(), Some(frame_count), 0);
+        assert_eq!(next_chunk(&results), last_chunk);
+        resume_sender.send(()).unwrap();
+        assert_eq!(next_chunk(&results), first_chunk);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn animation_stream_can_start_partway() {
+        let path = animation("partway", 40);
+        let (_resume_sender, resume) = unbounded();
+        let (result_sender, results) = unbounded();
+        TaskManager::start_stream(
+            0,
+            &path,
+            ImageFormat::Gif,
+            Vec::new(),
+            35,
+            resume,
+            &result_sender,
+        );
+
+        assert_eq!(next_chunk(&results), ((35..40).collect(), Some(40), 0));
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,165 @@
+use std::cmp::Reverse;
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+
+/// How urgently a task is needed. Workers always take the most urgent one first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    /// Gallery thumbnails, made when nothing else is waiting.
+    Thumbnail,
+    /// Images next to the selected one, loaded ahead of time.
+    Neighbor,
+    /// What is on screen right now.
+    Visible,
+}
+
+struct Queued<T> {
+    priority: Priority,
+    /// Tasks of the same priority run in the order they were added.
+    order: u64,
+    task: T,
+}
+
+struct State<T> {
+    tasks: Vec<Queued<T>>,
+    next_order: u64,
+    closed: bool,
+}
+
+/// Tasks waiting for a worker, shared between the thread adding them and the workers,
+/// which block in [`Self::pop`] until there is something to do. Unlike a channel,
+/// waiting tasks can still be re-ranked or taken back out.
+pub struct TaskQueue<T> {
+    state: Mutex<State<T>>,
+    available: Condvar,
+}
+
+impl<T> TaskQueue<T> {
+    pub const fn new() -> Self {
+        Self {
+            state: Mutex::new(State {
+                tasks: Vec::new(),
+                next_order: 0,
+                closed: false,
+            }),
+            available: Condvar::new(),
+        }
+    }
+
+    /// A worker panicking while holding the lock leaves the list itself intact.
+    fn state(&self) -> MutexGuard<'_, State<T>> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn push(&self, priority: Priority, task: T) {
+        let mut state = self.state();
+        let order = state.next_order;
+        state.next_order += 1;
+        state.tasks.push(Queued {
+            priority,
+            order,
+            task,
+        });
+        drop(state);
+        self.available.notify_one();
+    }
+
+    /// Takes the most urgent task, waiting until there is one. `None` once the queue is closed.
+    pub fn pop(&self) -> Option<T> {
+        let mut state = self.state();
+        loop {
+            if state.closed {
+                return None;
+            }
+            let next = state
+                .tasks
+                .iter()
+                .enumerate()
+                .max_by_key(|(_, queued)| (queued.priority, Reverse(queued.order)))
+                .map(|(index, _)| index);
+            if let Some(index) = next {
+                return Some(state.tasks.swap_remove(index).task);
+            }
+            state = self
+                .available
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    /// Gives every waiting task the priority `priority_of` says. Tasks it gives `None`
+    /// are no longer wanted, they are taken out and returned.
+    pub fn reprioritize(&self, mut priority_of: impl FnMut(&T) -> Option<Priority>) -> Vec<T> {
+        let mut state = self.state();
+        let mut cancelled = Vec::new();
+        for mut queued in std::mem::take(&mut state.tasks) {
+            match priority_of(&queued.task) {
+                Some(priority) => {
+                    queued.priority = priority;
+                    state.tasks.push(queued);
+                }
+                None => cancelled.push(queued.task),
+            }
+        }
+        cancelled
+    }
+
+    /// Stops the workers. Tasks still waiting are dropped.
+    pub fn close(&self) {
+        let mut state = self.state();
+        state.closed = true;
+        state.tasks.clear();
+        drop(state);
+        self.available.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn pops_by_priority_then_in_order() {
+        let queue = TaskQueue::new();
+        queue.push(Priority::Thumbnail, "thumbnail");
+        queue.push(Priority::Neighbor, "first neighbor");
+        queue.push(Priority::Visible, "visible");
+        queue.push(Priority::Neighbor, "second neighbor");
+        let popped: Vec<_> = (0..4).filter_map(|_| queue.pop()).collect();
+        assert_eq!(
+            popped,
+            ["visible", "first neighbor", "second neighbor", "thumbnail"]
+        );
+    }
+
+    #[test]
+    fn reprioritize_reranks_and_returns_cancelled() {
+        let queue = TaskQueue::new();
+        for task in 0..4 {
+            queue.push(Priority::Neighbor, task);
+        }
+        let cancelled = queue.reprioritize(|task| match task {
+            0 | 3 => None,
+            2 => Some(Priority::Visible),
+            _ => Some(Priority::Thumbnail),
+        });
+        assert_eq!(cancelled, [0, 3]);
+        assert_eq!(queue.pop(), Some(2));
+        assert_eq!(queue.pop(), Some(1));
+    }
+
+    #[test]
+    fn close_stops_waiting_workers() {
+        let queue = Arc::new(TaskQueue::<u32>::new());
+        let worker = thread::spawn({
+            let queue = Arc::clone(&queue);
+            move || queue.pop()
+        });
+        queue.close();
+        assert_eq!(worker.join().unwrap(), None);
+        queue.push(Priority::Visible, 1);
+        assert_eq!(queue.pop(), None);
+    }
+}
@@ -0,0 +1,238 @@
+use std::error::Error;
+use std::fmt::Write;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use image::RgbaImage;
+
+/// Where the freedesktop.org Thumbnail Managing Standard keeps each size, by the
+/// largest side of the thumbnails in it.
+const FOLDERS: [(&str, u32); 4] = [
+    ("normal", 128),
+    ("large", 256),
+    ("x-large", 512),
+    ("xx-large", 1024),
+];
+
+/// The folder for thumbnails of `size`, and the size they are made at in there.
+pub fn folder_for(size: u32) -> (&'static str, u32) {
+    FOLDERS
+        .iter()
+        .copied()
+        .find(|(_, max)| size <= *max)
+        .unwrap_or(FOLDERS[FOLDERS.len() - 1])
+}
+
+fn cache_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+        .map(|dir| dir.join("thumbnails"))
+}
+
+/// The `file://` URI of an absolute path, escaped like `GLib` does it, so the names
+/// match the ones other programs use.
+fn file_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for byte in path.as_os_str().as_encoded_bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~!$&'()*+,;=:@/".contains(byte) {
+            uri.push(char::from(*byte));
+        } else {
+            let _ = write!(uri, "%{byte:02X}");
+        }
+    }
+    uri
+}
+
+/// Where the thumbnail of a file goes, and what it has to say about the file to be current.
+struct Entry {
+    path: PathBuf,
+    uri: String,
+    mtime: u64,
+}
+
+impl Entry {
+    fn new(file: &Path, size: u32) -> Option<Self> {
+        let file = file.canonicalize().ok()?;
+        let cache_dir = cache_dir()?;
+        // Thumbnails of thumbnails would only pile up.
+        if file.starts_with(&cache_dir) {
+            return None;
+        }
+        let modified = file.metadata().ok()?.modified().ok()?;
+        let mtime = modified
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .ok()?
+            .as_secs();
+        let uri = file_uri(&file);
+        let name = format!("{:x}.png", md5::compute(&uri));
+        let (folder, _) = folder_for(size);
+        Some(Self {
+            path: cache_dir.join(folder).join(name),
+            uri,
+            mtime,
+        })
+    }
+}
+
+/// A thumbnail made before, by us or another program, unless the file changed since.
+pub fn load(file: &Path, size: u32) -> Option<RgbaImage> {
+    let entry = Entry::new(file, size)?;
+    let reader = png::Decoder::new(File::open(&entry.path).ok()?)
+        .read_info()
+        .ok()?;
+    let text = &reader.info().uncompressed_latin1_text;
+    let field = |keyword: &str| {
+        text.iter()
+            .find(|chunk| chunk.keyword == keyword)
+            .map(|chunk| chunk.text.as_str())
+    };
+    let mtime = field("Thumb::MTime").and_then(|mtime| mtime.parse::<u64>().ok());
+    if field("Thumb::URI") != Some(entry.uri.as_str()) || mtime != Some(entry.mtime) {
+        return None;
+    }
+    image::open(&entry.path).ok().map(|image| image.to_rgba8())
+}
+
+/// Keeps a thumbnail for later runs and other programs. It is written to a temporary
+/// file first, so nobody reads it half-written.
+pub fn save(file: &Path, size: u32, thumbnail: &RgbaImage) -> Result<(), Box<dyn Error>> {
+    let Some(entry) = Entry::new(file, size) else {
+        return Ok(());
+    };
+    let Some(dir) = entry.path.parent() else {
+        return Ok(());
+    };
+    create_private_dir(dir)?;
+
+    let temporary = entry
+        .path
+        .with_extension(format!("png.{}.tmp", std::process::id()));
+    let written = write(&entry, &temporary, thumbnail);
+    if written.is_err() {
+        // Whatever part of it made it to disk is of no use to anyone.
+        let _ = fs::remove_file(&temporary);
+    }
+    written
+}
+
+fn write(entry: &Entry, temporary: &Path, thumbnail: &RgbaImage) -> Result<(), Box<dyn Error>> {
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(File::create(temporary)?),
+        thumbnail.width(),
+        thumbnail.height(),
+    );
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.add_text_chunk("Thumb::URI".to_string(), entry.uri.clone())?;
+    encoder.add_text_chunk("Thumb::MTime".to_string(), entry.mtime.to_string())?;
+    encoder.add_text_chunk("Software".to_string(), "pfiew".to_string())?;
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(thumbnail.as_raw())?;
+    writer.finish()?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(temporary, fs::Permissions::from_mode(0o600))?;
+    }
+    fs::rename(temporary, &entry.path)?;
+    Ok(())
+}
+
+/// The standard asks for the cache to only be readable by its owner.
+fn create_private_dir(dir: &Path) -> std::io::Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder.create(dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::OnceLock;
+    use std::time::Duration;
+
+    use super::*;
+
+    /// A scratch directory for the test files, with the thumbnail cache in it. The cache
+    /// location is process wide, so every test shares it.
+    fn scratch() -> &'static Path {
+        static SCRATCH: OnceLock<PathBuf> = OnceLock::new();
+        SCRATCH.get_or_init(|| {
+            let dir = std::env::temp_dir().join(format!("pfiew-thumbnails-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            std::env::set_var("XDG_CACHE_HOME", dir.join("cache"));
+            dir
+        })
+    }
+
+    fn photo(name: &str) -> PathBuf {
+        let path = scratch().join(name);
+        fs::write(&path, name).unwrap();
+        path
+    }
+
+    fn thumbnail() -> RgbaImage {
+        RgbaImage::from_pixel(4, 3, image::Rgba([10, 20, 30, 255]))
+    }
+
+    #[test]
+    fn uris_are_escaped_like_glib() {
+        let uri = file_uri(Path::new("/home/me/My Photos/caf\u{e9} #1 (2)?.png"));
+        assert_eq!(
+            uri,
+            "file:///home/me/My%20Photos/caf%C3%A9%20%231%20(2)%3F.png"
+        );
+    }
+
+    #[test]
+    fn saved_thumbnails_load_until_the_file_changes() {
+        let file = photo("changes.png");
+        save(&file, 128, &thumbnail()).unwrap();
+        assert_eq!(load(&file, 128), Some(thumbnail()));
+        // Other sizes live in other folders.
+        assert_eq!(load(&file, 256), None);
+
+        let modified = file.metadata().unwrap().modified().unwrap();
+        let touched = File::options().write(true).open(&file).unwrap();
+        touched
+            .set_modified(modified + Duration::from_secs(90))
+            .unwrap();
+        assert_eq!(load(&file, 128), None);
+    }
+
+    #[test]
+    fn thumbnails_of_other_files_are_not_used() {
+        let file = photo("original.png");
+        let other = photo("other.png");
+        save(&file, 128, &thumbnail()).unwrap();
+        let stolen = Entry::new(&other, 128).unwrap().path;
+        fs::copy(Entry::new(&file, 128).unwrap().path, stolen).unwrap();
+        assert_eq!(load(&other, 128), None);
+    }
+
+    #[test]
+    fn failed_saves_leave_no_temporary_file() {
+        let file = photo("blocked.png");
+        let target = Entry::new(&file, 512).unwrap().path;
+        // Nothing can be renamed onto a directory.
+        fs::create_dir_all(&target).unwrap();
+        assert!(save(&file, 512, &thumbnail()).is_err());
+        let folder = target.parent().unwrap();
+        let leftovers: Vec<_> = fs::read_dir(folder)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .filter(|name| name.to_string_lossy().ends_with(".tmp"))
+            .collect();
+        assert!(leftovers.is_empty(), "{leftovers:?}");
+    }
+}
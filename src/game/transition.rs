@@ -0,0 +1,152 @@
+use std::time::{Duration, Instant};
+
+use glam::{UVec2, Vec2};
+use speedy2d::color::Color;
+use speedy2d::image::ImageHandle;
+use speedy2d::Graphics2D;
+use strum_macros::{Display, EnumString};
+
+use crate::config::Config;
+use crate::game::world::EntityId;
+
+/// How one image gives way to the next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum TransitionKind {
+    /// Cut straight to the next image.
+    None,
+    /// Fade the next image in over the previous one.
+    Crossfade,
+    /// Push the previous image out sideways, in the direction of travel.
+    Slide,
+}
+
+/// An image as it was last drawn.
+struct Picture {
+    image: ImageHandle,
+    corners: [Vec2; 4],
+}
+
+/// Keeps what was on screen around while the selection changes, so the previous
+/// image stays up until the next one is decoded and then gives way to it.
+pub struct Transition {
+    kind: TransitionKind,
+    duration: Duration,
+    /// The selected image and its index, as of the last update.
+    id: Option<EntityId>,
+    index: usize,
+    current: Option<Picture>,
+    /// What was shown before the selection changed, until the transition is over.
+    previous: Option<Picture>,
+    /// When the current image was ready, which is when the transition starts.
+    started: Option<Instant>,
+    forward: bool,
+}
+
+impl Transition {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            kind: config.transition,
+            duration: Duration::from_secs_f32(config.transition_duration.max(0.0)),
+            id: None,
+            index: 0,
+            current: None,
+            previous: None,
+            started: None,
+            forward: true,
+        }
+    }
+
+    /// Called every frame with the selected image, and its frame and corners on screen
+    /// once it is loaded.
+    pub fn update(
+        &mut self,
+        id: EntityId,
+        index: usize,
+        count: usize,
+        current: Option<(ImageHandle, [Vec2; 4])>,
+        now: Instant,
+    ) {
+        if self.id != Some(id) {
+            // Going from the last image to the first is still going forward.
+            self.forward = if self.index + 1 == count && index == 0 {
+                true
+            } else if self.index == 0 && index + 1 == count {
+                false
+            } else {
+                index > self.index
+            };
+            if let Some(current) = self.current.take() {
+                self.previous = Some(current);
+            }
+            self.id = Some(id);
+            self.started = None;
+        }
+        self.index = index;
+        if let Some((image, corners)) = current {
+            self.started.get_or_insert(now);
+            self.current = Some(Picture { image, corners });
+        }
+        if self.progress(now) >= 1.0 {
+            self.previous = None;
+        }
+    }
+
+    /// Stops showing anything until the next image is ready.
+    pub fn clear(&mut self) {
+        self.current = None;
+        self.previous = None;
+        self.started = None;
+    }
+
+    /// From 0 when the current image is ready to 1 when the previous one is gone.
+    fn progress(&self, now: Instant) -> f32 {
+        let Some(started) = self.started else {
+            return 0.0;
+        };
+        if self.kind == TransitionKind::None || self.duration.is_zero() {
+            return 1.0;
+        }
+        (now.duration_since(started).as_secs_f32() / self.duration.as_secs_f32()).min(1.0)
+    }
+
+    pub fn draw(&self, graphics: &mut Graphics2D, viewport_size: UVec2) {
+        let progress = self.progress(Instant::now());
+        let width = viewport_size.as_vec2().x;
+        let direction = if self.forward { -1.0 } else { 1.0 };
+        if let Some(previous) = &self.previous {
+            match self.kind {
+                TransitionKind::Slide => {
+                    let offset = Vec2::new(direction * width * progress, 0.0);
+                    draw_picture(graphics, previous, offset, 1.0);
+                }
+                // The next image fades in over it, so the background never shows through.
+                TransitionKind::Crossfade | TransitionKind::None => {
+                    draw_picture(graphics, previous, Vec2::ZERO, 1.0);
+                }
+            }
+        }
+        if let Some(current) = &self.current {
+            match self.kind {
+                TransitionKind::Slide => {
+                    let offset = Vec2::new(-direction * width * (1.0 - progress), 0.0);
+                    draw_picture(graphics, current, offset, 1.0);
+                }
+                TransitionKind::Crossfade => {
+                    draw_picture(graphics, current, Vec2::ZERO, progress);
+                }
+                TransitionKind::None => draw_picture(graphics, current, Vec2::ZERO, 1.0),
+            }
+        }
+    }
+}
+
+fn draw_picture(graphics: &mut Graphics2D, picture: &Picture, offset: Vec2, opacity: f32) {
+    let tint = Color::from_rgba(1.0, 1.0, 1.0, opacity);
+    graphics.draw_quad_image_tinted_four_color(
+        picture.corners.map(|corner| corner + offset),
+        [tint; 4],
+        [Vec2::ZERO, Vec2::X, Vec2::ONE, Vec2::Y],
+        &picture.image,
+    );
+}
@@ -0,0 +1,136 @@
+use std::path::{Path, PathBuf};
+
+pub enum WatchEvent {
+    /// A file was written and closed, either a new one or an existing one that changed.
+    Changed(PathBuf),
+    Removed(PathBuf),
+    Renamed {
+        from: PathBuf,
+        to: PathBuf,
+    },
+}
+
+#[cfg(target_os = "linux")]
+pub use inotify_watcher::Watcher;
+
+/// Watches directories with inotify. Reading is non-blocking, so it can be polled every frame.
+#[cfg(target_os = "linux")]
+mod inotify_watcher {
+    use std::collections::{HashMap, HashSet};
+    use std::io::ErrorKind;
+    use std::path::{Path, PathBuf};
+
+    use inotify::{EventMask, Inotify, WatchDescriptor, WatchMask};
+
+    use super::WatchEvent;
+
+    pub struct Watcher {
+        inotify: Inotify,
+        dirs: HashMap<WatchDescriptor, PathBuf>,
+        watched: HashSet<PathBuf>,
+        buffer: Vec<u8>,
+    }
+
+    impl Watcher {
+        pub fn new() -> Option<Self> {
+            match Inotify::init() {
+                Ok(inotify) => Some(Self {
+                    inotify,
+                    dirs: HashMap::new(),
+                    watched: HashSet::new(),
+                    buffer: vec![0; 4096],
+                }),
+                Err(err) => {
+                    eprintln!("Can't watch for file changes: {err}");
+                    None
+                }
+            }
+        }
+
+        pub fn watch(&mut self, dir: &Path) {
+            if self.watched.contains(dir) {
+                return;
+            }
+            self.watched.insert(dir.to_path_buf());
+            let mask = WatchMask::CLOSE_WRITE
+                | WatchMask::DELETE
+                | WatchMask::MOVED_FROM
+                | WatchMask::MOVED_TO;
+            match self.inotify.watches().add(dir, mask) {
+                Ok(wd) => {
+                    self.dirs.insert(wd, dir.to_path_buf());
+                }
+                Err(err) => eprintln!("Can't watch {}: {err}", dir.display()),
+            }
+        }
+
+        pub fn poll(&mut self) -> Vec<WatchEvent> {
+            let mut changes = Vec::new();
+            // Renames come as a MOVED_FROM and MOVED_TO pair sharing a cookie.
+            let mut moved_from: HashMap<u32, PathBuf> = HashMap::new();
+            loop {
+                let events = match self.inotify.read_events(&mut self.buffer) {
+                    Ok(events) => events,
+                    Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                    Err(err) => {
+                        eprintln!("Failed to read file changes: {err}");
+                        break;
+                    }
+                };
+                let mut any = false;
+                for event in events {
+                    any = true;
+                    if event.mask.contains(EventMask::ISDIR) {
+                        continue;
+                    }
+                    let (Some(dir), Some(name)) = (self.dirs.get(&event.wd), event.name) else {
+                        continue;
+                    };
+                    let path = dir.join(name);
+                    if event.mask.contains(EventMask::CLOSE_WRITE) {
+                        changes.push(WatchEvent::Changed(path));
+                    } else if event.mask.contains(EventMask::DELETE) {
+                        changes.push(WatchEvent::Removed(path));
+                    } else if event.mask.contains(EventMask::MOVED_FROM) {
+                        moved_from.insert(event.cookie, path);
+                    } else if event.mask.contains(EventMask::MOVED_TO) {
+                        match moved_from.remove(&event.cookie) {
+                            Some(from) => changes.push(WatchEvent::Renamed { from, to: path }),
+                            None => changes.push(WatchEvent::Changed(path)),
+                        }
+                    }
+                }
+                if !any {
+                    break;
+                }
+            }
+            // Moved out of the watched directories.
+            changes.extend(moved_from.into_values().map(WatchEvent::Removed));
+            changes
+        }
+    }
+}
+
+/// File watching is only implemented with inotify, elsewhere the list stays as scanned.
+#[cfg(not(target_os = "linux"))]
+pub struct Watcher;
+
+#[cfg(not(target_os = "linux"))]
+impl Watcher {
+    pub const fn new() -> Option<Self> {
+        None
+    }
+
+    pub fn watch(&mut self, _dir: &Path) {}
+
+    pub fn poll(&mut self) -> Vec<WatchEvent> {
+        Vec::new()
+    }
+}
+
+/// The directory to watch for a scanned file.
+pub fn watched_dir(path: &Path) -> &Path {
+    path.parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."))
+}
@@ -1,29 +1,343 @@
-use std::collections::HashMap;
-use std::path::PathBuf;
+use image::ImageFormat;
 use speedy2d::image::ImageHandle;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::game::camera::Orientation;
+use crate::game::filters::Filter;
+use crate::game::sorting;
 
 pub type EntityId = usize;
 
+/// Most frames of one animation kept at once. Longer animations are decoded again,
+/// a chunk at a time, as they play.
+pub const FRAME_WINDOW: usize = 64;
+/// How many frames one decode task makes.
+pub const FRAME_CHUNK: usize = FRAME_WINDOW / 2;
+
+/// How far along loading an image is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadState<'a> {
+    /// Not decoded yet, or evicted from the cache since.
+    Pending,
+    /// At least the first frame is ready to draw.
+    Loaded,
+    Failed(&'a str),
+}
+
+pub struct Frame {
+    pub image: ImageHandle,
+    pub delay: Duration,
+}
+
+struct CachedImage {
+    /// Frames by index. Still images have one, long animations only those around
+    /// the playback position.
+    frames: BTreeMap<usize, Frame>,
+    /// Decoded pixels before filtering, so filters can be swapped without decoding again.
+    /// Animations don't keep them, they are decoded again instead.
+    pixels: Option<Arc<Vec<u8>>>,
+    filters: Vec<Filter>,
+    /// How many frames there are, unknown until an animation was decoded to the end.
+    frame_count: Option<usize>,
+    bytes: usize,
+    last_used: u64,
+}
+
+struct CachedThumbnail {
+    image: ImageHandle,
+    last_used: u64,
+}
+
+/// Decoded RGBA, which is what the texture holds on the GPU.
+fn texture_bytes(image: &ImageHandle) -> usize {
+    let size = image.size();
+    size.x as usize * size.y as usize * 4
+}
+
 pub struct World {
     next_id: EntityId,
     assets: HashMap<EntityId, PathBuf>,
-    images: HashMap<EntityId, ImageHandle>,
+    paths: HashMap<PathBuf, EntityId>,
+    /// Display order. Ids stay the same when entries are added, removed or moved around.
+    order: Vec<EntityId>,
+    images: HashMap<EntityId, CachedImage>,
+    /// Rotations and flips, only for images that have been turned.
+    orientations: HashMap<EntityId, Orientation>,
+    /// Small versions for the gallery, with a budget of their own so browsing the gallery
+    /// doesn't push the images around the selected one out.
+    thumbnails: HashMap<EntityId, CachedThumbnail>,
+    /// Why images that couldn't be loaded failed, so they aren't tried again every frame.
+    failures: HashMap<EntityId, String>,
+    /// File sizes looked up for the overlay, `None` if the file couldn't be read.
+    file_sizes: HashMap<EntityId, Option<u64>>,
+    /// What the decoder found in each file, whatever its extension says.
+    formats: HashMap<EntityId, ImageFormat>,
+
+    cache_budget: usize,
+    cache_used: usize,
+    thumbnail_budget: usize,
+    thumbnail_used: usize,
+    tick: u64,
 }
 
 impl World {
     pub const fn len(&self) -> usize {
-        self.next_id
+        self.order.len()
     }
 
-    pub fn spawn_asset(&mut self, path: PathBuf) -> EntityId {
+    /// Adds an image at the end, unless the path is already known.
+    pub fn spawn_asset(&mut self, path: PathBuf) -> Option<EntityId> {
+        if self.paths.contains_key(&path) {
+            return None;
+        }
         let id = self.next_id;
         self.next_id += 1;
+        self.paths.insert(path.clone(), id);
         self.assets.insert(id, path);
-        id
+        self.order.push(id);
+        Some(id)
+    }
+
+    /// Adds an image among the others from its directory, ordered by name.
+    pub fn insert_asset(&mut self, path: PathBuf) -> Option<EntityId> {
+        let id = self.spawn_asset(path)?;
+        self.order.pop();
+        let path = &self.assets[&id];
+        let siblings: Vec<usize> = (0..self.order.len())
+            .filter(|index| self.assets[&self.order[*index]].parent() == path.parent())
+            .collect();
+        let index = siblings
+            .iter()
+            .find(|index| sorting::compare_names(&self.assets[&self.order[**index]], path).is_gt())
+            .copied()
+            .or_else(|| siblings.last().map(|last| last + 1))
+            .unwrap_or(self.order.len());
+        self.order.insert(index, id);
+        Some(id)
+    }
+
+    pub fn remove_asset(&mut self, id: EntityId) {
+        if let Some(path) = self.assets.remove(&id) {
+            self.paths.remove(&path);
+        }
+        self.order.retain(|other| *other != id);
+        self.orientations.remove(&id);
+        self.drop_thumbnail(id);
+        self.drop_image(id);
+    }
+
+    /// Moves an image to a new path, replacing the image that was there, if any.
+    pub fn rename_asset(&mut self, id: EntityId, path: PathBuf) {
+        if let Some(replaced) = self.find_path(&path).filter(|other| *other != id) {
+            self.remove_asset(replaced);
+        }
+        if let Some(old) = self.assets.insert(id, path.clone()) {
+            self.paths.remove(&old);
+        }
+        self.paths.insert(path, id);
+    }
+
+    /// Forgets the decoded image or the failure to decode it, so it gets loaded from disk again.
+    pub fn drop_image(&mut self, id: EntityId) {
+        self.failures.remove(&id);
+        self.file_sizes.remove(&id);
+        self.formats.remove(&id);
+        if let Some(cached) = self.images.remove(&id) {
+            self.cache_used -= cached.bytes;
+        }
+    }
+
+    /// Every image with its path, in display order.
+    pub fn entries(&self) -> Vec<(EntityId, PathBuf)> {
+        self.order
+            .iter()
+            .map(|id| (*id, self.assets[id].clone()))
+            .collect()
+    }
+
+    /// Puts the images in a new order. Images removed since `order` was made are left out,
+    /// ones added since are kept at the end.
+    pub fn reorder(&mut self, order: Vec<EntityId>) {
+        let mut order: Vec<EntityId> = order
+            .into_iter()
+            .filter(|id| self.assets.contains_key(id))
+            .collect();
+        let sorted: HashSet<EntityId> = order.iter().copied().collect();
+        order.extend(self.order.iter().filter(|id| !sorted.contains(id)));
+        self.order = order;
+    }
+
+    pub fn order(&self) -> &[EntityId] {
+        &self.order
+    }
+
+    /// The entity shown at `index` in display order.
+    pub fn id_at(&self, index: usize) -> Option<EntityId> {
+        self.order.get(index).copied()
+    }
+
+    pub fn index_of(&self, id: EntityId) -> Option<usize> {
+        self.order.iter().position(|other| *other == id)
+    }
+
+    /// Looks up the size of the file, unless it is known already.
+    pub fn read_file_size(&mut self, id: EntityId) {
+        if let (Some(path), None) = (self.assets.get(&id), self.file_sizes.get(&id)) {
+            let size = path.metadata().ok().map(|metadata| metadata.len());
+            self.file_sizes.insert(id, size);
+        }
+    }
+
+    pub fn file_size(&self, id: EntityId) -> Option<u64> {
+        self.file_sizes.get(&id).copied().flatten()
     }
 
-    pub fn insert_image(&mut self, id: EntityId, image: ImageHandle) {
-        self.images.insert(id, image);
+    pub fn set_format(&mut self, id: EntityId, format: ImageFormat) {
+        if self.assets.contains_key(&id) {
+            self.formats.insert(id, format);
+        }
+    }
+
+    pub fn format(&self, id: EntityId) -> Option<ImageFormat> {
+        self.formats.get(&id).copied()
+    }
+
+    pub fn find_path(&self, path: &Path) -> Option<EntityId> {
+        self.paths.get(path).copied()
+    }
+
+    pub fn insert_image(
+        &mut self,
+        id: EntityId,
+        image: ImageHandle,
+        pixels: Arc<Vec<u8>>,
+        filters: Vec<Filter>,
+    ) {
+        let bytes = texture_bytes(&image) + pixels.len();
+        let frame = Frame {
+            image,
+            delay: Duration::ZERO,
+        };
+        let frames = BTreeMap::from([(0, frame)]);
+        self.insert_cached(id, frames, Some(pixels), filters, Some(1), bytes);
+    }
+
+    /// Adds a streamed animation frame. A frame made with other filters than the cached
+    /// ones replaces them.
+    pub fn insert_frame(
+        &mut self,
+        id: EntityId,
+        index: usize,
+        image: ImageHandle,
+        delay: Duration,
+        filters: Vec<Filter>,
+    ) {
+        let bytes = texture_bytes(&image);
+        let frame = Frame { image, delay };
+        match self.images.get_mut(&id) {
+            Some(cached) if cached.filters == filters => {
+                if let Some(old) = cached.frames.insert(index, frame) {
+                    let old_bytes = texture_bytes(&old.image);
+                    cached.bytes -= old_bytes;
+                    self.cache_used -= old_bytes;
+                }
+                cached.bytes += bytes;
+                self.cache_used += bytes;
+            }
+            _ => {
+                let frames = BTreeMap::from([(index, frame)]);
+                self.insert_cached(id, frames, None, filters, None, bytes);
+            }
+        }
+    }
+
+    /// Drops the frames of an animation that come up last from `current` on, until
+    /// no more than [`FRAME_WINDOW`] are left.
+    pub fn trim_frames(&mut self, id: EntityId, current: usize) {
+        let Some(cached) = self.images.get_mut(&id) else {
+            return;
+        };
+        while cached.frames.len() > FRAME_WINDOW {
+            let last = cached
+                .frames
+                .keys()
+                .copied()
+                .max_by_key(|index| frames_until(current, *index, cached.frame_count));
+            if let Some(frame) = last.and_then(|index| cached.frames.remove(&index)) {
+                let bytes = texture_bytes(&frame.image);
+                cached.bytes -= bytes;
+                self.cache_used -= bytes;
+            }
+        }
+    }
+
+    /// The first frame coming up soon after `current` that isn't decoded, if any.
+    /// Soon enough that the chunk decoded from there won't be trimmed again right away.
+    pub fn missing_frame(&self, id: EntityId, current: usize) -> Option<usize> {
+        let cached = self.images.get(&id)?;
+        (current..current + FRAME_WINDOW - FRAME_CHUNK)
+            .map(|index| cached.frame_count.map_or(index, |count| index % count))
+            .find(|index| !cached.frames.contains_key(index))
+    }
+
+    /// Records why an image couldn't be loaded. An animation that broke off partway keeps
+    /// playing the frames it got.
+    pub fn set_failed(&mut self, id: EntityId, error: String) {
+        if let Some(cached) = self.images.get_mut(&id) {
+            if cached.frame_count.is_none() {
+                cached.frame_count = cached.frames.last_key_value().map(|(index, _)| index + 1);
+            }
+        } else if self.assets.contains_key(&id) {
+            self.failures.insert(id, error);
+        }
+    }
+
+    pub fn load_state(&self, id: EntityId) -> LoadState<'_> {
+        if self.images.contains_key(&id) {
+            LoadState::Loaded
+        } else if let Some(error) = self.failures.get(&id) {
+            LoadState::Failed(error)
+        } else {
+            LoadState::Pending
+        }
+    }
+
+    /// Called once an animation was decoded to the end.
+    pub fn set_frame_count(&mut self, id: EntityId, frame_count: usize) {
+        if let Some(cached) = self.images.get_mut(&id) {
+            cached.frame_count = Some(frame_count);
+        }
+    }
+
+    fn insert_cached(
+        &mut self,
+        id: EntityId,
+        frames: BTreeMap<usize, Frame>,
+        pixels: Option<Arc<Vec<u8>>>,
+        filters: Vec<Filter>,
+        frame_count: Option<usize>,
+        bytes: usize,
+    ) {
+        // The file may have been removed while it was decoding.
+        if !self.assets.contains_key(&id) {
+            return;
+        }
+        self.tick += 1;
+        let cached = CachedImage {
+            frames,
+            pixels,
+            filters,
+            frame_count,
+            bytes,
+            last_used: self.tick,
+        };
+        self.cache_used += bytes;
+        if let Some(old) = self.images.insert(id, cached) {
+            self.cache_used -= old.bytes;
+        }
     }
 
     pub fn get_path(&self, id: EntityId) -> Option<&PathBuf> {
@@ -31,14 +345,152 @@ impl World {
     }
 
     pub fn get_image(&self, id: EntityId) -> Option<&ImageHandle> {
-        self.images.get(&id)
+        self.images
+            .get(&id)
+            .and_then(|cached| cached.frames.values().next())
+            .map(|frame| &frame.image)
+    }
+
+    pub fn frame(&self, id: EntityId, index: usize) -> Option<&Frame> {
+        self.images
+            .get(&id)
+            .and_then(|cached| cached.frames.get(&index))
+    }
+
+    /// How many frames the image has, if known yet.
+    pub fn frame_count(&self, id: EntityId) -> Option<usize> {
+        self.images.get(&id).and_then(|cached| cached.frame_count)
+    }
+
+    pub fn get_pixels(&self, id: EntityId) -> Option<&Arc<Vec<u8>>> {
+        self.images
+            .get(&id)
+            .and_then(|cached| cached.pixels.as_ref())
+    }
+
+    pub fn get_filters(&self, id: EntityId) -> Option<&[Filter]> {
+        self.images.get(&id).map(|cached| cached.filters.as_slice())
+    }
+
+    pub fn insert_thumbnail(&mut self, id: EntityId, image: ImageHandle) {
+        if !self.assets.contains_key(&id) {
+            return;
+        }
+        self.tick += 1;
+        self.thumbnail_used += texture_bytes(&image);
+        let cached = CachedThumbnail {
+            image,
+            last_used: self.tick,
+        };
+        if let Some(old) = self.thumbnails.insert(id, cached) {
+            self.thumbnail_used -= texture_bytes(&old.image);
+        }
     }
 
-    pub fn new() -> Self {
+    pub fn get_thumbnail(&self, id: EntityId) -> Option<&ImageHandle> {
+        self.thumbnails.get(&id).map(|cached| &cached.image)
+    }
+
+    /// Forgets the thumbnail of a changed file, so a new one gets made.
+    pub fn drop_thumbnail(&mut self, id: EntityId) {
+        if let Some(cached) = self.thumbnails.remove(&id) {
+            self.thumbnail_used -= texture_bytes(&cached.image);
+        }
+    }
+
+    /// Marks the thumbnails in `wanted` as used, then drops the ones least recently
+    /// wanted until they fit their budget. Thumbnails in `wanted` are never dropped.
+    pub fn evict_thumbnails(&mut self, wanted: &[EntityId]) {
+        self.tick += 1;
+        for id in wanted {
+            if let Some(cached) = self.thumbnails.get_mut(id) {
+                cached.last_used = self.tick;
+            }
+        }
+        if self.thumbnail_used <= self.thumbnail_budget {
+            return;
+        }
+        let mut candidates: Vec<(u64, EntityId)> = self
+            .thumbnails
+            .iter()
+            .filter(|(_, cached)| cached.last_used < self.tick)
+            .map(|(id, cached)| (cached.last_used, *id))
+            .collect();
+        candidates.sort_unstable();
+
+        for (_, id) in candidates {
+            if self.thumbnail_used <= self.thumbnail_budget {
+                break;
+            }
+            self.drop_thumbnail(id);
+        }
+    }
+
+    pub fn get_orientation(&self, id: EntityId) -> Orientation {
+        self.orientations.get(&id).copied().unwrap_or_default()
+    }
+
+    pub fn set_orientation(&mut self, id: EntityId, orientation: Orientation) {
+        self.orientations.insert(id, orientation);
+    }
+
+    /// Marks the image as viewed, so it is the last candidate for eviction.
+    pub fn touch(&mut self, id: EntityId) {
+        if let Some(cached) = self.images.get_mut(&id) {
+            self.tick += 1;
+            cached.last_used = self.tick;
+        }
+    }
+
+    /// Drops least recently viewed images until the cache fits its budget.
+    /// Images in `keep` are never evicted, even if that leaves the cache over budget.
+    pub fn evict(&mut self, keep: &[EntityId]) {
+        if self.cache_used <= self.cache_budget {
+            return;
+        }
+        let mut candidates: Vec<(u64, EntityId)> = self
+            .images
+            .iter()
+            .filter(|(id, _)| !keep.contains(id))
+            .map(|(id, cached)| (cached.last_used, *id))
+            .collect();
+        candidates.sort_unstable();
+
+        for (_, id) in candidates {
+            if self.cache_used <= self.cache_budget {
+                break;
+            }
+            self.drop_image(id);
+        }
+    }
+
+    pub fn new(cache_budget: usize, thumbnail_budget: usize) -> Self {
         Self {
             next_id: 0,
             assets: HashMap::new(),
+            paths: HashMap::new(),
+            order: Vec::new(),
             images: HashMap::new(),
+            orientations: HashMap::new(),
+            thumbnails: HashMap::new(),
+            failures: HashMap::new(),
+            file_sizes: HashMap::new(),
+            formats: HashMap::new(),
+
+            cache_budget,
+            cache_used: 0,
+            thumbnail_budget,
+            thumbnail_used: 0,
+            tick: 0,
         }
     }
 }
+
+/// How many frames playback moves through from `current` until it shows `index`.
+/// With the frame count unknown, frames before `current` come last.
+fn frames_until(current: usize, index: usize, frame_count: Option<usize>) -> usize {
+    frame_count.map_or_else(
+        || index.checked_sub(current).unwrap_or(usize::MAX),
+        |count| (index + count - current % count) % count,
+    )
+}
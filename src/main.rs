@@ -13,33 +13,46 @@ use glam::u32::UVec2;
 
 mod app;
 use app::App;
+mod bindings;
+mod cli;
+use cli::Command;
 mod config;
 use config::Config;
 
 mod game;
 
 fn main() {
-    let mut config = Config::new("config.ini");
     let args: Vec<String> = args().skip(1).collect();
-    if args.is_empty() {
-        config.input = "./".to_string();
-    } else {
-        let arg = &args[0];
-        if arg.contains("--input=") {
-            let (_before, after) = arg.split_once("--input=").unwrap();
-            after.clone_into(&mut config.input);
-        } else {
-            config.input = arg.to_string();
+    let config = match cli::parse(&args) {
+        Ok(Command::Run(options)) => {
+            let mut config = Config::new(&options.config_path);
+            options.apply(&mut config);
+            config
+        }
+        Ok(Command::Help) => {
+            print!("{}", cli::USAGE);
+            return;
+        }
+        Ok(Command::Version) => {
+            println!("pfiew {}", env!("CARGO_PKG_VERSION"));
+            return;
+        }
+        Err(err) => {
+            eprintln!("{err}\n\n{}", cli::USAGE);
+            std::process::exit(2);
         }
-    }
+    };
 
     let window_size = UVec2::new(config.window_width, config.window_height);
-    let window_pixels = WindowSize::PhysicalPixels(window_size);
+    let options = if config.fullscreen {
+        WindowCreationOptions::new_fullscreen_borderless()
+    } else {
+        let window_pixels = WindowSize::PhysicalPixels(window_size);
+        WindowCreationOptions::new_windowed(window_pixels, Some(WindowPosition::Center))
+    };
     let window = Window::new_with_options(
         &config.title,
-        WindowCreationOptions::new_windowed(window_pixels, Some(WindowPosition::Center))
-            .with_decorations(true)
-            .with_transparent(false),
+        options.with_decorations(true).with_transparent(false),
     )
     .expect("Wasn't able to create a window!");
     window.run_loop(App::new(window_size, config));